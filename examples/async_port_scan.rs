@@ -3,6 +3,7 @@ use std::time::Duration;
 use std::thread;
 use netscan::async_io::PortScanner;
 use netscan::setting::{ScanType, Destination};
+use netscan::result::{ScanEvent, PortStatus};
 use async_io;
 
 fn main() {
@@ -13,6 +14,7 @@ fn main() {
     // Add scan target
     let dst_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 8));
     //let dst: Destination = Destination::new(dst_ip, vec![22, 80, 443, 5000, 8080]);
+    //let dst: Destination = Destination::new_with_top_ports(dst_ip, 100);
     let dst: Destination = Destination::new_with_port_range(dst_ip, 1, 1000);
     port_scanner.add_destination(dst);
     // Set options
@@ -28,10 +30,26 @@ fn main() {
         })
     });
     // Print progress
-    while let Ok(_socket_addr) = rx.lock().unwrap().recv() {
-        //println!("Check: {}", socket_addr);
+    while let Ok(event) = rx.lock().unwrap().recv() {
+        match event {
+            ScanEvent::Started { total } => println!("Started: {:?} probes", total),
+            ScanEvent::PortState(ip_addr, port_info) => {
+                match port_info.status {
+                    PortStatus::Open => println!("Open: {}:{}", ip_addr, port_info.port),
+                    _ => {},
+                }
+            },
+            ScanEvent::Finished { status } => {
+                println!("Finished: {:?}", status);
+                break;
+            },
+            _ => {},
+        }
     }
-    let result = handle.join().unwrap();
+    let result = match handle.join().unwrap() {
+        Ok(result) => result,
+        Err(e) => panic!("Error running scan: {}", e),
+    };
     // Print results 
     println!("Status: {:?}", result.scan_status);
     println!("Results:");
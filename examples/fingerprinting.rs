@@ -23,7 +23,10 @@ fn main() {
     fingerprinter.add_probe_target(probe_target1);
     fingerprinter.add_probe_target(probe_target2);
     fingerprinter.set_full_probe();
-    let results = fingerprinter.probe();
+    let results = match fingerprinter.probe() {
+        Ok(results) => results,
+        Err(e) => panic!("Error running probe: {}", e),
+    };
     for result in results {
         println!("{}", result.ip_addr);
         println!("{:?}", result.icmp_echo_result);
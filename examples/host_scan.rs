@@ -1,8 +1,9 @@
 use netscan::blocking::HostScanner;
 use netscan::setting::{ScanType, Destination};
+use netscan::result::ScanEvent;
+use netscan::target::parse_targets;
 use std::time::Duration;
 use std::net::{IpAddr, Ipv4Addr};
-use ipnet::Ipv4Net;
 use std::thread;
 
 fn main() {
@@ -10,12 +11,10 @@ fn main() {
         Ok(scanner) => (scanner),
         Err(e) => panic!("Error creating scanner: {}", e),
     };
-    let net: Ipv4Net = Ipv4Net::new(Ipv4Addr::new(192, 168, 1, 0), 24).unwrap();
-    let nw_addr = Ipv4Net::new(net.network(), 24).unwrap();
-    let hosts: Vec<Ipv4Addr> = nw_addr.hosts().collect();
+    let hosts: Vec<IpAddr> = parse_targets("192.168.1.1-254", host_scanner.get_src_ip()).unwrap();
     // Add scan target
     for host in hosts {
-        let dst: Destination = Destination::new(IpAddr::V4(host), vec![]);
+        let dst: Destination = Destination::new(host, vec![]);
         host_scanner.add_destination(dst);
     }
     // Set options
@@ -30,10 +29,21 @@ fn main() {
         host_scanner.scan()
     });
     // Print progress
-    while let Ok(_socket_addr) = rx.lock().unwrap().recv() {
-        //println!("Check: {}", socket_addr);
+    while let Ok(event) = rx.lock().unwrap().recv() {
+        match event {
+            ScanEvent::Started { total } => println!("Started: {:?} probes", total),
+            ScanEvent::HostUp(host_info) => println!("Up: {}", host_info.ip_addr),
+            ScanEvent::Finished { status } => {
+                println!("Finished: {:?}", status);
+                break;
+            },
+            _ => {},
+        }
     }
-    let result = handle.join().unwrap();
+    let result = match handle.join().unwrap() {
+        Ok(result) => result,
+        Err(e) => panic!("Error running scan: {}", e),
+    };
     // Print results 
     println!("Status: {:?}", result.scan_status);
     println!("UP Hosts:");
@@ -1,5 +1,6 @@
 use netscan::blocking::PortScanner;
 use netscan::setting::{ScanType, Destination};
+use netscan::result::{ScanEvent, PortStatus};
 use std::time::Duration;
 use std::net::{IpAddr, Ipv4Addr};
 use std::thread;
@@ -12,6 +13,7 @@ fn main() {
     // Add scan target
     let dst_ip: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 8));
     //let dst: Destination = Destination::new(dst_ip, vec![22, 80, 443, 5000, 8080]);
+    //let dst: Destination = Destination::new_with_top_ports(dst_ip, 100);
     let dst: Destination = Destination::new_with_port_range(dst_ip, 1, 1000);
     port_scanner.add_destination(dst);
     // Multiple target hosts can be configured
@@ -30,10 +32,26 @@ fn main() {
         port_scanner.scan()
     });
     // Print progress
-    while let Ok(_socket_addr) = rx.lock().unwrap().recv() {
-        //println!("Check: {}", socket_addr);
+    while let Ok(event) = rx.lock().unwrap().recv() {
+        match event {
+            ScanEvent::Started { total } => println!("Started: {:?} probes", total),
+            ScanEvent::PortState(ip_addr, port_info) => {
+                match port_info.status {
+                    PortStatus::Open => println!("Open: {}:{}", ip_addr, port_info.port),
+                    _ => {},
+                }
+            },
+            ScanEvent::Finished { status } => {
+                println!("Finished: {:?}", status);
+                break;
+            },
+            _ => {},
+        }
     }
-    let result = handle.join().unwrap();
+    let result = match handle.join().unwrap() {
+        Ok(result) => result,
+        Err(e) => panic!("Error running scan: {}", e),
+    };
     // Print results 
     println!("Status: {:?}", result.scan_status);
     println!("Results:");
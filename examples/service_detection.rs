@@ -17,7 +17,10 @@ fn main() {
     port_scanner.set_timeout(Duration::from_millis(10000));
     port_scanner.set_wait_time(Duration::from_millis(100));
     port_scanner.set_send_rate(Duration::from_millis(1));
-    let result = port_scanner.scan();
+    let result = match port_scanner.scan() {
+        Ok(result) => result,
+        Err(e) => panic!("Error running scan: {}", e),
+    };
     println!("{:?}", result);
     for (ip, _ports) in result.result_map.clone() {
         println!("{}", ip);
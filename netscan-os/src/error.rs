@@ -0,0 +1,58 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+
+/// Error of fingerprinter setup and probe task
+#[derive(Debug)]
+pub enum Error {
+    /// Datalink channel requires privileges (root or CAP_NET_RAW)
+    PermissionDenied(io::Error),
+    /// No network interface with the source IP address
+    InterfaceNotFound(IpAddr),
+    /// Failed to open the datalink channel on the interface
+    ChannelOpen(io::Error),
+    /// Failed to resolve the MAC address of the default gateway
+    GatewayResolution(String),
+    /// Other I/O error
+    Io(io::Error),
+}
+
+impl Error {
+    // Permission error from opening a datalink channel
+    pub(crate) fn from_channel_error(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(error),
+            _ => Error::ChannelOpen(error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PermissionDenied(e) => write!(f, "Permission denied. Datalink channel requires root or CAP_NET_RAW: {}", e),
+            Error::InterfaceNotFound(ip_addr) => write!(f, "Network interface not found for {}", ip_addr),
+            Error::ChannelOpen(e) => write!(f, "Failed to open datalink channel: {}", e),
+            Error::GatewayResolution(msg) => write!(f, "Failed to resolve default gateway: {}", msg),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PermissionDenied(e) | Error::ChannelOpen(e) | Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(error),
+            _ => Error::Io(error),
+        }
+    }
+}
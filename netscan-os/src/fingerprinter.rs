@@ -8,6 +8,7 @@ use super::send;
 use super::receive;
 use super::setting::{ProbeTarget, ProbeType, ProbeSetting};
 use super::result::{ProbeStatus, ProbeResult};
+use super::error::Error;
 
 const DEFAULT_SRC_PORT: u16 = 54433;
 
@@ -41,7 +42,7 @@ pub struct Fingerprinter {
 
 impl Fingerprinter {
     /// Create new fingerprinter with interfece IP
-    pub fn new(src_ip: IpAddr) -> Result<Fingerprinter, String> {
+    pub fn new(src_ip: IpAddr) -> Result<Fingerprinter, Error> {
         let mut if_index: u32 = 0;
         let mut if_name: String = String::new();
         let mut src_mac: MacAddr = MacAddr::zero();
@@ -56,14 +57,14 @@ impl Fingerprinter {
             }   
         }
         if if_index == 0 || if_name.is_empty() || src_mac == MacAddr::zero() {
-            return Err(String::from("Failed to create Fingerprinter. Network Interface not found."));
+            return Err(Error::InterfaceNotFound(src_ip));
         }
         let dst_mac: MacAddr = match default_net::get_default_gateway() {
             Ok(default_gateway) => {
                 let octets = default_gateway.mac_addr.octets();
                 MacAddr::new(octets[0], octets[1], octets[2], octets[3], octets[4], octets[5])
             },
-            Err(_) => return Err(String::from("Failed to get gateway mac")),
+            Err(e) => return Err(Error::GatewayResolution(e)),
         };
         let fingerprinter = Fingerprinter {
             if_index: if_index,
@@ -83,7 +84,7 @@ impl Fingerprinter {
     }
 
     /// Create new fingerprinter with interfece IP and gateway IP
-    pub fn new_with_gateway_ip(src_ip: IpAddr, gateway_ip: IpAddr) -> Result<Fingerprinter, String> {
+    pub fn new_with_gateway_ip(src_ip: IpAddr, gateway_ip: IpAddr) -> Result<Fingerprinter, Error> {
         let mut if_index: u32 = 0;
         let mut if_name: String = String::new();
         let mut src_mac: MacAddr = MacAddr::zero();
@@ -98,19 +99,18 @@ impl Fingerprinter {
             }   
         }
         if if_index == 0 || if_name.is_empty() || src_mac == MacAddr::zero() {
-            return Err(String::from("Failed to create Fingerprinter. Network Interface not found."));
+            return Err(Error::InterfaceNotFound(src_ip));
         }
-        let interfaces = pnet_datalink::interfaces();
-        let interface = interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == if_index).next().expect("Failed to get Interface");
+        let interface = get_interface(if_index, src_ip)?;
         let dst_mac: MacAddr = match gateway_ip {
             IpAddr::V4(ip) =>{
-                let dst_mac: MacAddr = get_mac_through_arp(&interface, ip);
+                let dst_mac: MacAddr = get_mac_through_arp(&interface, ip)?;
                 if dst_mac == pnet_datalink::MacAddr::zero() {
-                    return Err(String::from("Failed to create Fingerprinter. Invalid Gateway IP address."));
+                    return Err(Error::GatewayResolution(format!("No ARP reply from {}", gateway_ip)));
                 }
                 dst_mac
             },
-            IpAddr::V6(_) => return Err(String::from("Failed to create Fingerprinter. Invalid Gateway IP address.")),
+            IpAddr::V6(_) => return Err(Error::GatewayResolution(format!("Gateway must be an IPv4 address: {}", gateway_ip))),
         };
         let fingerprinter = Fingerprinter {
             if_index: if_index,
@@ -178,9 +178,8 @@ impl Fingerprinter {
         self.probe_results.clone()
     }
     /// Run probe with the current settings
-    pub fn run_probe(&mut self) {
-        let interfaces = pnet_datalink::interfaces();
-        let interface = interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == self.if_index).next().expect("Failed to get Interface");
+    pub fn run_probe(&mut self) -> Result<(), Error> {
+        let interface = get_interface(self.if_index, self.src_ip)?;
         for dst in self.probe_targets.clone() {
             let mut probe_setting: ProbeSetting = ProbeSetting {
                 src_mac: self.src_mac.parse::<pnet_datalink::MacAddr>().unwrap(),
@@ -193,18 +192,26 @@ impl Fingerprinter {
                 wait_time: self.wait_time,
                 send_rate: self.send_rate,
             };
-            let result: ProbeResult = probe(&interface, &mut probe_setting);
+            let result: ProbeResult = probe(&interface, &mut probe_setting)?;
             self.probe_results.push(result);
         }
+        Ok(())
     }
     /// Run probe and return result
-    pub fn probe(&mut self) -> Vec<ProbeResult> {
-        self.run_probe();
-        self.probe_results.clone()
+    pub fn probe(&mut self) -> Result<Vec<ProbeResult>, Error> {
+        self.run_probe()?;
+        Ok(self.probe_results.clone())
     }
 }
 
-fn probe(interface: &pnet_datalink::NetworkInterface, probe_setting: &ProbeSetting) -> ProbeResult {
+fn get_interface(if_index: u32, src_ip: IpAddr) -> Result<pnet_datalink::NetworkInterface, Error> {
+    match pnet_datalink::interfaces().into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == if_index).next() {
+        Some(interface) => Ok(interface),
+        None => Err(Error::InterfaceNotFound(src_ip)),
+    }
+}
+
+fn probe(interface: &pnet_datalink::NetworkInterface, probe_setting: &ProbeSetting) -> Result<ProbeResult, Error> {
     let probe_result: Arc<Mutex<ProbeResult>> = Arc::new(Mutex::new(ProbeResult::new_with_types(probe_setting.probe_target.ip_addr, probe_setting.probe_types.clone())));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let probe_status: Arc<Mutex<ProbeStatus>> = Arc::new(Mutex::new(ProbeStatus::Ready));
@@ -218,19 +225,23 @@ fn probe(interface: &pnet_datalink::NetworkInterface, probe_setting: &ProbeSetti
         linux_fanout: None,
         promiscuous: false,
     };
-    let (mut tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
+    let (mut tx, mut rx) = open_channel(interface, config)?;
     rayon::join(|| send::send_packets(&mut tx, &probe_setting, &stop),
                 || receive::receive_packets(&mut rx, &probe_setting, &probe_result, &stop, &probe_status)
     );
     let result: ProbeResult = probe_result.lock().unwrap().clone();
-    return result;
+    return Ok(result);
 }
 
-fn get_mac_through_arp(interface: &pnet_datalink::NetworkInterface, target_ip: Ipv4Addr) -> MacAddr {
+fn open_channel(interface: &pnet_datalink::NetworkInterface, config: pnet_datalink::Config) -> Result<(Box<dyn pnet_datalink::DataLinkSender>, Box<dyn pnet_datalink::DataLinkReceiver>), Error> {
+    match pnet_datalink::channel(interface, config) {
+        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => Ok((tx, rx)),
+        Ok(_) => Err(Error::ChannelOpen(std::io::Error::new(std::io::ErrorKind::Unsupported, "Unknown channel type"))),
+        Err(e) => Err(Error::from_channel_error(e)),
+    }
+}
+
+fn get_mac_through_arp(interface: &pnet_datalink::NetworkInterface, target_ip: Ipv4Addr) -> Result<MacAddr, Error> {
     let source_ip = interface
         .ips
         .iter()
@@ -239,19 +250,16 @@ fn get_mac_through_arp(interface: &pnet_datalink::NetworkInterface, target_ip: I
             IpAddr::V4(ip) => ip,
             _ => unreachable!(),
         })
-        .unwrap();
+        .ok_or(Error::GatewayResolution(format!("No IPv4 address on {}", interface.name)))?;
+    let source_mac: MacAddr = interface.mac.ok_or(Error::GatewayResolution(format!("No MAC address on {}", interface.name)))?;
 
-    let (mut sender, mut receiver) = match pnet_datalink::channel(&interface, Default::default()) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
+    let (mut sender, mut receiver) = open_channel(interface, Default::default())?;
 
     let mut ethernet_buffer = [0u8; 42];
     let mut ethernet_packet = pnet_packet::ethernet::MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
 
     ethernet_packet.set_destination(pnet_datalink::MacAddr::broadcast());
-    ethernet_packet.set_source(interface.mac.unwrap());
+    ethernet_packet.set_source(source_mac);
     ethernet_packet.set_ethertype(pnet_packet::ethernet::EtherTypes::Arp);
 
     let mut arp_buffer = [0u8; 28];
@@ -262,24 +270,30 @@ fn get_mac_through_arp(interface: &pnet_datalink::NetworkInterface, target_ip: I
     arp_packet.set_hw_addr_len(6);
     arp_packet.set_proto_addr_len(4);
     arp_packet.set_operation(pnet_packet::arp::ArpOperations::Request);
-    arp_packet.set_sender_hw_addr(interface.mac.unwrap());
+    arp_packet.set_sender_hw_addr(source_mac);
     arp_packet.set_sender_proto_addr(source_ip);
     arp_packet.set_target_hw_addr(pnet_datalink::MacAddr::zero());
     arp_packet.set_target_proto_addr(target_ip);
 
     ethernet_packet.set_payload(arp_packet.packet_mut());
 
-    sender.send_to(ethernet_packet.packet(), None).unwrap().unwrap();
+    match sender.send_to(ethernet_packet.packet(), None) {
+        Some(Ok(_)) => {},
+        Some(Err(e)) => return Err(Error::Io(e)),
+        None => return Err(Error::Io(std::io::Error::other("Failed to send ARP request"))),
+    }
 
     let mut target_mac_addr: pnet_datalink::MacAddr = pnet_datalink::MacAddr::zero();
 
     for _ in 0..2 {
-        let buf = receiver.next().unwrap();
-        let arp = pnet_packet::arp::ArpPacket::new(&buf[pnet_packet::ethernet::MutableEthernetPacket::minimum_packet_size()..]).unwrap();
-        if arp.get_sender_hw_addr() != interface.mac.unwrap() {
+        let buf = receiver.next()?;
+        let arp = buf.get(pnet_packet::ethernet::MutableEthernetPacket::minimum_packet_size()..)
+            .and_then(pnet_packet::arp::ArpPacket::new)
+            .ok_or(Error::GatewayResolution(String::from("Malformed ARP reply")))?;
+        if arp.get_sender_hw_addr() != source_mac {
             target_mac_addr = arp.get_sender_hw_addr();
             break;
         }
     }
-    return target_mac_addr;
+    return Ok(target_mac_addr);
 }
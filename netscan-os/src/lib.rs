@@ -4,7 +4,9 @@ mod result;
 mod receive;
 mod send;
 mod fingerprinter;
+mod error;
 
 pub use setting::*;
 pub use result::*;
 pub use fingerprinter::*;
+pub use error::Error;
@@ -1,11 +1,17 @@
-use std::net::{IpAddr, SocketAddr};
+use std::net::IpAddr;
 use std::collections::HashSet;
 use std::time::{Duration, Instant};
 use std::sync::{Mutex, Arc};
 use std::sync::mpsc::{channel ,Sender, Receiver};
-use crate::setting::{Destination, ScanType, DEFAULT_SRC_PORT, ScanSetting, DEFAULT_HOSTS_CONCURRENCY, DEFAULT_PORTS_CONCURRENCY};
-use crate::result::{HostScanResult, PortScanResult, ScanStatus};
+use crate::setting::{Destination, ScanType, UdpPayloadDatabase, DynamicSendRate, RateLimit, FallbackPolicy, DEFAULT_SRC_PORT, ScanSetting, DEFAULT_HOSTS_CONCURRENCY, DEFAULT_PORTS_CONCURRENCY};
+use crate::result::{self, HostScanResult, PortScanResult, ScanStatus, ScanTechnique, ScanEvent};
 use crate::async_io::{scan_hosts, scan_ports};
+use crate::timing::RateController;
+use crate::target::TargetStream;
+use crate::cookie::CookieKey;
+use crate::error::Error;
+use crate::privilege;
+use crate::cancel::CancelToken;
 
 /// Async Host Scanner 
 #[derive(Clone, Debug)]
@@ -26,6 +32,8 @@ pub struct HostScanner {
     pub destinations: Vec<Destination>,
     /// Scan Type 
     pub scan_type: ScanType,
+    /// UDP payloads by destination port 
+    pub udp_payloads: UdpPayloadDatabase,
     /// Number of host scans to run concurrently
     pub hosts_concurrency: usize,
     /// Timeout setting for entire scan task 
@@ -34,12 +42,30 @@ pub struct HostScanner {
     pub wait_time: Duration,
     /// Packet sending interval(0 for unlimited) 
     pub send_rate: Duration,
+    /// Congestion-aware send rate. Replaces the fixed send rate if set 
+    pub dynamic_send_rate: Option<DynamicSendRate>,
+    /// Hard cap on sending rate in packets or bits per second 
+    pub rate_limit: Option<RateLimit>,
+    /// Derive probe timeouts and waiting time from the measured RTT 
+    pub adaptive_timing: bool,
+    /// Number of retransmissions for probes without answer 
+    pub retry_count: u8,
+    /// Interleave hosts and shuffle ports instead of sending in order 
+    pub randomize: bool,
+    /// Seed for the randomized order. Random if not set 
+    pub random_seed: Option<u64>,
+    /// Lazy source of additional destinations. Replies are matched by cookie instead of per-probe state 
+    pub target_stream: Option<TargetStream>,
+    /// Policy when the scan type requires privileges that are not available 
+    pub fallback_policy: FallbackPolicy,
+    /// Handle to cancel the running scan 
+    pub cancel_token: CancelToken,
     /// Host Scan Result 
     pub scan_result: HostScanResult,
-    /// Sender for progress messaging
-    pub tx: Arc<Mutex<Sender<SocketAddr>>>,
-    /// Receiver for progress messaging
-    pub rx: Arc<Mutex<Receiver<SocketAddr>>>,
+    /// Sender for progress events
+    pub tx: Arc<Mutex<Sender<ScanEvent>>>,
+    /// Receiver for progress events
+    pub rx: Arc<Mutex<Receiver<ScanEvent>>>,
 }
 
 /// Async Port Scanner 
@@ -61,6 +87,8 @@ pub struct PortScanner {
     pub destinations: Vec<Destination>,
     /// Scan Type 
     pub scan_type: ScanType,
+    /// UDP payloads by destination port 
+    pub udp_payloads: UdpPayloadDatabase,
     /// Number of host scans to run concurrently
     pub hosts_concurrency: usize,
     /// Number of port scans to run concurrently
@@ -71,19 +99,37 @@ pub struct PortScanner {
     pub wait_time: Duration,
     /// Packet sending interval(0 for unlimited) 
     pub send_rate: Duration,
+    /// Congestion-aware send rate. Replaces the fixed send rate if set 
+    pub dynamic_send_rate: Option<DynamicSendRate>,
+    /// Hard cap on sending rate in packets or bits per second 
+    pub rate_limit: Option<RateLimit>,
+    /// Derive probe timeouts and waiting time from the measured RTT 
+    pub adaptive_timing: bool,
+    /// Number of retransmissions for probes without answer 
+    pub retry_count: u8,
+    /// Interleave hosts and shuffle ports instead of sending in order 
+    pub randomize: bool,
+    /// Seed for the randomized order. Random if not set 
+    pub random_seed: Option<u64>,
+    /// Lazy source of additional destinations. Replies are matched by cookie instead of per-probe state 
+    pub target_stream: Option<TargetStream>,
+    /// Policy when the scan type requires privileges that are not available 
+    pub fallback_policy: FallbackPolicy,
+    /// Handle to cancel the running scan 
+    pub cancel_token: CancelToken,
     /// Port Scan Result 
     pub scan_result: PortScanResult,
-    /// Sender for progress messaging
-    pub tx: Arc<Mutex<Sender<SocketAddr>>>,
-    /// Receiver for progress messaging
-    pub rx: Arc<Mutex<Receiver<SocketAddr>>>,
+    /// Sender for progress events
+    pub tx: Arc<Mutex<Sender<ScanEvent>>>,
+    /// Receiver for progress events
+    pub rx: Arc<Mutex<Receiver<ScanEvent>>>,
 }
 
 impl HostScanner {
     /// Create new HostScanner with source IP address
     /// 
     /// Initialized with default value based on the specified IP address
-    pub fn new(src_ip: IpAddr) -> Result<HostScanner, String> {
+    pub fn new(src_ip: IpAddr) -> Result<HostScanner, Error> {
         let mut if_index: u32 = 0;
         let mut if_name: String = String::new();
         let mut src_mac: pnet_datalink::MacAddr = pnet_datalink::MacAddr::zero();
@@ -98,7 +144,7 @@ impl HostScanner {
             }   
         }
         if if_index == 0 || if_name.is_empty() || src_mac == pnet_datalink::MacAddr::zero() {
-            return Err(String::from("Failed to create Scanner. Network Interface not found."));
+            return Err(Error::InterfaceNotFound(src_ip));
         }
         let (tx, rx) = channel();
         let host_scanner = HostScanner {
@@ -110,10 +156,20 @@ impl HostScanner {
             src_port: DEFAULT_SRC_PORT,
             destinations: vec![],
             scan_type: ScanType::IcmpPingScan,
+            udp_payloads: UdpPayloadDatabase::default(),
             hosts_concurrency: DEFAULT_HOSTS_CONCURRENCY,
             timeout: Duration::from_millis(30000),
             wait_time: Duration::from_millis(200),
             send_rate: Duration::from_millis(1),
+            dynamic_send_rate: None,
+            rate_limit: None,
+            adaptive_timing: false,
+            retry_count: 0,
+            randomize: false,
+            random_seed: None,
+            target_stream: None,
+            fallback_policy: FallbackPolicy::Disabled,
+            cancel_token: CancelToken::new(),
             scan_result: HostScanResult::new(),
             tx: Arc::new(Mutex::new(tx)),
             rx: Arc::new(Mutex::new(rx)),
@@ -148,6 +204,18 @@ impl HostScanner {
     pub fn get_scan_type(&self) -> ScanType {
         self.scan_type.clone()
     } 
+    /// Set UDP payload database
+    pub fn set_udp_payloads(&mut self, udp_payloads: UdpPayloadDatabase){
+        self.udp_payloads = udp_payloads;
+    }
+    /// Add UDP payload for the destination port
+    pub fn add_udp_payload(&mut self, port: u16, payload: Vec<u8>){
+        self.udp_payloads.add_payload(port, payload);
+    }
+    /// Get UDP payload database
+    pub fn get_udp_payloads(&self) -> UdpPayloadDatabase {
+        self.udp_payloads.clone()
+    }
     /// Set timeout 
     pub fn set_timeout(&mut self, timeout: Duration){
         self.timeout = timeout;
@@ -172,6 +240,107 @@ impl HostScanner {
     pub fn get_send_rate(&self) -> Duration {
         self.send_rate.clone()
     }
+    /// Set dynamic send rate
+    /// 
+    /// Packet sending interval adapts to the response ratio between the floor and ceiling.
+    pub fn set_dynamic_send_rate(&mut self, dynamic_send_rate: DynamicSendRate){
+        self.dynamic_send_rate = Some(dynamic_send_rate);
+    }
+    /// Get dynamic send rate
+    pub fn get_dynamic_send_rate(&self) -> Option<DynamicSendRate> {
+        self.dynamic_send_rate
+    }
+    /// Set rate limit
+    /// 
+    /// Token bucket shared by all senders of the scan. Applies on top of the send rate.
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit){
+        self.rate_limit = Some(rate_limit);
+    }
+    /// Get rate limit
+    pub fn get_rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+    /// Set adaptive timing
+    /// 
+    /// Estimate smoothed RTT and variance per host from replies, 
+    /// and derive probe timeouts and waiting time from them instead of the fixed values.
+    pub fn set_adaptive_timing(&mut self, adaptive_timing: bool){
+        self.adaptive_timing = adaptive_timing;
+    }
+    /// Get adaptive timing
+    pub fn get_adaptive_timing(&self) -> bool {
+        self.adaptive_timing
+    }
+    /// Set retry count
+    /// 
+    /// Probes without answer after the waiting time are resent up to this number of times.
+    pub fn set_retry_count(&mut self, retry_count: u8){
+        self.retry_count = retry_count;
+    }
+    /// Get retry count
+    pub fn get_retry_count(&self) -> u8 {
+        self.retry_count
+    }
+    /// Set randomize
+    /// 
+    /// Send probes in a random order by cyclic permutation, interleaving hosts. 
+    /// Memory use does not grow with the number of targets.
+    pub fn set_randomize(&mut self, randomize: bool){
+        self.randomize = randomize;
+    }
+    /// Get randomize
+    pub fn get_randomize(&self) -> bool {
+        self.randomize
+    }
+    /// Set random seed
+    /// 
+    /// The same seed gives the same order for the same targets.
+    pub fn set_random_seed(&mut self, random_seed: u64){
+        self.random_seed = Some(random_seed);
+    }
+    /// Get random seed
+    pub fn get_random_seed(&self) -> Option<u64> {
+        self.random_seed
+    }
+    /// Set target stream
+    /// 
+    /// Destinations are pulled from the stream while sending, after the destinations already set. 
+    /// Replies are validated by a keyed hash of the probe instead of a table of sent probes, 
+    /// so memory does not grow with the number of targets. 
+    /// Randomized order, retransmission, RTT measurement and reporting of unanswered ports do not apply. 
+    /// The stream is consumed by the scan.
+    pub fn set_target_stream(&mut self, target_stream: TargetStream){
+        self.target_stream = Some(target_stream);
+    }
+    /// Get target stream
+    pub fn get_target_stream(&self) -> Option<TargetStream> {
+        self.target_stream.clone()
+    }
+    /// Set fallback policy
+    /// 
+    /// Without raw socket privileges, TCP SYN scan is run as TCP connect scan 
+    /// and ICMP or TCP ping as TCP connect ping. ICMP ping prefers datagram ICMP socket if allowed. 
+    /// The technique used is recorded in the result.
+    pub fn set_fallback_policy(&mut self, fallback_policy: FallbackPolicy){
+        self.fallback_policy = fallback_policy;
+    }
+    /// Get fallback policy
+    pub fn get_fallback_policy(&self) -> FallbackPolicy {
+        self.fallback_policy
+    }
+    /// Set cancel token
+    /// 
+    /// Replaces a token that has already been cancelled, before running the scan again.
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken){
+        self.cancel_token = cancel_token;
+    }
+    /// Get cancel token
+    /// 
+    /// Clone of the token shared with the scan. Cancel it from another thread or task 
+    /// to stop sending and get the partial result with ScanStatus::Cancelled.
+    pub fn get_cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
     /// Set hosts concurrency
     pub fn set_hosts_concurrency(&mut self, concurrency: usize){
         self.hosts_concurrency = concurrency;
@@ -181,44 +350,79 @@ impl HostScanner {
         self.scan_result.clone()
     }
     /// Get progress receiver
-    pub fn get_progress_receiver(&self) -> Arc<Mutex<Receiver<SocketAddr>>> {
+    /// 
+    /// Delivers ScanEvent from Started to Finished while the scan is running
+    pub fn get_progress_receiver(&self) -> Arc<Mutex<Receiver<ScanEvent>>> {
         self.rx.clone()
     }
     /// Run Host Scan
-    pub async fn run_scan(&mut self){
+    /// 
+    /// Fails if the socket or datalink channel cannot be opened, e.g. without privileges.
+    pub async fn run_scan(&mut self) -> Result<(), Error> {
+        let (destinations, target_stream) = match &self.target_stream {
+            Some(target_stream) => (vec![], Some(TargetStream::new(self.destinations.clone().into_iter().chain(target_stream.clone())))),
+            None => (self.destinations.clone(), None),
+        };
         let mut ip_set: HashSet<IpAddr> = HashSet::new();
-        for dst in self.destinations.clone() {
+        for dst in destinations.clone() {
             ip_set.insert(dst.dst_ip);
         }
+        let technique: ScanTechnique = privilege::get_scan_technique(&self.scan_type, self.src_ip, self.fallback_policy)?;
         let scan_setting: ScanSetting = ScanSetting {
             if_index: self.if_index.clone(),
             src_mac: pnet_datalink::MacAddr::from(self.src_mac),
             dst_mac: pnet_datalink::MacAddr::from(self.dst_mac),
             src_ip: self.src_ip.clone(),
             src_port: self.src_port.clone(),
-            destinations: self.destinations.clone(),
+            destinations: destinations,
             ip_set: ip_set,
+            target_stream: target_stream,
+            cookie_key: CookieKey::new(),
             timeout: self.timeout.clone(),
             wait_time: self.wait_time.clone(),
-            send_rate: self.timeout.clone(),
-            scan_type: self.scan_type.clone(),
+            send_rate: self.send_rate.clone(),
+            rate_controller: self.dynamic_send_rate.map(|dynamic_send_rate| Arc::new(Mutex::new(RateController::new(dynamic_send_rate.min_pps, dynamic_send_rate.max_pps)))),
+            rate_limiter: self.rate_limit.map(|rate_limit| Arc::new(Mutex::new(rate_limit.into_token_bucket()))),
+            adaptive_timing: self.adaptive_timing,
+            retry_count: self.retry_count,
+            retransmission: false,
+            random_seed: if self.randomize { Some(self.random_seed.unwrap_or(rand::random::<u64>())) } else { None },
+            scan_type: technique.scan_type.clone(),
+            privileged: technique.privileged,
+            echo_identifier: None,
+            udp_payloads: self.udp_payloads.clone(),
+            cancel_token: self.cancel_token.clone(),
             hosts_concurrency: self.hosts_concurrency,
             ports_concurrency: DEFAULT_PORTS_CONCURRENCY,
         };
+        result::send_event(&self.tx, ScanEvent::Started { total: scan_setting.get_probe_count() });
         let start_time = Instant::now();
-        let mut result: HostScanResult = scan_hosts(scan_setting, &self.tx).await;
+        let mut result: HostScanResult = match scan_hosts(scan_setting, &self.tx).await {
+            Ok(result) => result,
+            Err(e) => {
+                self.scan_result = HostScanResult::new();
+                self.scan_result.scan_status = ScanStatus::Error;
+                result::send_event(&self.tx, ScanEvent::Finished { status: ScanStatus::Error });
+                return Err(e);
+            },
+        };
         result.scan_time = Instant::now().duration_since(start_time);
-        if result.scan_time > self.timeout {
+        if self.cancel_token.is_cancelled() {
+            result.scan_status = ScanStatus::Cancelled;
+        } else if result.scan_time > self.timeout {
             result.scan_status = ScanStatus::Timeout;
         } else {
             result.scan_status = ScanStatus::Done;
         }
+        result.technique = Some(technique);
+        result::send_event(&self.tx, ScanEvent::Finished { status: result.scan_status.clone() });
         self.scan_result = result;
+        Ok(())
     }
     /// Run scan and return result
-    pub async fn scan(&mut self) -> HostScanResult {
-        self.run_scan().await;
-        self.scan_result.clone()
+    pub async fn scan(&mut self) -> Result<HostScanResult, Error> {
+        self.run_scan().await?;
+        Ok(self.scan_result.clone())
     }
 }
 
@@ -226,7 +430,7 @@ impl PortScanner {
     /// Create new PortScanner with source IP address
     /// 
     /// Initialized with default value based on the specified IP address
-    pub fn new(src_ip: IpAddr) -> Result<PortScanner, String> {
+    pub fn new(src_ip: IpAddr) -> Result<PortScanner, Error> {
         let mut if_index: u32 = 0;
         let mut if_name: String = String::new();
         let mut src_mac: pnet_datalink::MacAddr = pnet_datalink::MacAddr::zero();
@@ -241,7 +445,7 @@ impl PortScanner {
             }   
         }
         if if_index == 0 || if_name.is_empty() || src_mac == pnet_datalink::MacAddr::zero() {
-            return Err(String::from("Failed to create Scanner. Network Interface not found."));
+            return Err(Error::InterfaceNotFound(src_ip));
         }
         let (tx, rx) = channel();
         let port_scanner = PortScanner {
@@ -253,11 +457,21 @@ impl PortScanner {
             src_port: DEFAULT_SRC_PORT,
             destinations: vec![],
             scan_type: ScanType::TcpSynScan,
+            udp_payloads: UdpPayloadDatabase::default(),
             hosts_concurrency: DEFAULT_HOSTS_CONCURRENCY,
             ports_concurrency: DEFAULT_PORTS_CONCURRENCY,
             timeout: Duration::from_millis(30000),
             wait_time: Duration::from_millis(200),
             send_rate: Duration::from_millis(1),
+            dynamic_send_rate: None,
+            rate_limit: None,
+            adaptive_timing: false,
+            retry_count: 0,
+            randomize: false,
+            random_seed: None,
+            target_stream: None,
+            fallback_policy: FallbackPolicy::Disabled,
+            cancel_token: CancelToken::new(),
             scan_result: PortScanResult::new(),
             tx: Arc::new(Mutex::new(tx)),
             rx: Arc::new(Mutex::new(rx)),
@@ -292,6 +506,18 @@ impl PortScanner {
     pub fn get_scan_type(&self) -> ScanType {
         self.scan_type.clone()
     } 
+    /// Set UDP payload database
+    pub fn set_udp_payloads(&mut self, udp_payloads: UdpPayloadDatabase){
+        self.udp_payloads = udp_payloads;
+    }
+    /// Add UDP payload for the destination port
+    pub fn add_udp_payload(&mut self, port: u16, payload: Vec<u8>){
+        self.udp_payloads.add_payload(port, payload);
+    }
+    /// Get UDP payload database
+    pub fn get_udp_payloads(&self) -> UdpPayloadDatabase {
+        self.udp_payloads.clone()
+    }
     /// Set timeout 
     pub fn set_timeout(&mut self, timeout: Duration){
         self.timeout = timeout;
@@ -316,6 +542,107 @@ impl PortScanner {
     pub fn get_send_rate(&self) -> Duration {
         self.send_rate.clone()
     }
+    /// Set dynamic send rate
+    /// 
+    /// Packet sending interval adapts to the response ratio between the floor and ceiling.
+    pub fn set_dynamic_send_rate(&mut self, dynamic_send_rate: DynamicSendRate){
+        self.dynamic_send_rate = Some(dynamic_send_rate);
+    }
+    /// Get dynamic send rate
+    pub fn get_dynamic_send_rate(&self) -> Option<DynamicSendRate> {
+        self.dynamic_send_rate
+    }
+    /// Set rate limit
+    /// 
+    /// Token bucket shared by all senders of the scan. Applies on top of the send rate.
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit){
+        self.rate_limit = Some(rate_limit);
+    }
+    /// Get rate limit
+    pub fn get_rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+    /// Set adaptive timing
+    /// 
+    /// Estimate smoothed RTT and variance per host from replies, 
+    /// and derive probe timeouts and waiting time from them instead of the fixed values.
+    pub fn set_adaptive_timing(&mut self, adaptive_timing: bool){
+        self.adaptive_timing = adaptive_timing;
+    }
+    /// Get adaptive timing
+    pub fn get_adaptive_timing(&self) -> bool {
+        self.adaptive_timing
+    }
+    /// Set retry count
+    /// 
+    /// Probes without answer after the waiting time are resent up to this number of times.
+    pub fn set_retry_count(&mut self, retry_count: u8){
+        self.retry_count = retry_count;
+    }
+    /// Get retry count
+    pub fn get_retry_count(&self) -> u8 {
+        self.retry_count
+    }
+    /// Set randomize
+    /// 
+    /// Send probes in a random order by cyclic permutation, interleaving hosts. 
+    /// Memory use does not grow with the number of targets.
+    pub fn set_randomize(&mut self, randomize: bool){
+        self.randomize = randomize;
+    }
+    /// Get randomize
+    pub fn get_randomize(&self) -> bool {
+        self.randomize
+    }
+    /// Set random seed
+    /// 
+    /// The same seed gives the same order for the same targets.
+    pub fn set_random_seed(&mut self, random_seed: u64){
+        self.random_seed = Some(random_seed);
+    }
+    /// Get random seed
+    pub fn get_random_seed(&self) -> Option<u64> {
+        self.random_seed
+    }
+    /// Set target stream
+    /// 
+    /// Destinations are pulled from the stream while sending, after the destinations already set. 
+    /// Replies are validated by a keyed hash of the probe instead of a table of sent probes, 
+    /// so memory does not grow with the number of targets. 
+    /// Randomized order, retransmission, RTT measurement and reporting of unanswered ports do not apply. 
+    /// The stream is consumed by the scan.
+    pub fn set_target_stream(&mut self, target_stream: TargetStream){
+        self.target_stream = Some(target_stream);
+    }
+    /// Get target stream
+    pub fn get_target_stream(&self) -> Option<TargetStream> {
+        self.target_stream.clone()
+    }
+    /// Set fallback policy
+    /// 
+    /// Without raw socket privileges, TCP SYN scan is run as TCP connect scan 
+    /// and ICMP or TCP ping as TCP connect ping. ICMP ping prefers datagram ICMP socket if allowed. 
+    /// The technique used is recorded in the result.
+    pub fn set_fallback_policy(&mut self, fallback_policy: FallbackPolicy){
+        self.fallback_policy = fallback_policy;
+    }
+    /// Get fallback policy
+    pub fn get_fallback_policy(&self) -> FallbackPolicy {
+        self.fallback_policy
+    }
+    /// Set cancel token
+    /// 
+    /// Replaces a token that has already been cancelled, before running the scan again.
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken){
+        self.cancel_token = cancel_token;
+    }
+    /// Get cancel token
+    /// 
+    /// Clone of the token shared with the scan. Cancel it from another thread or task 
+    /// to stop sending and get the partial result with ScanStatus::Cancelled.
+    pub fn get_cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
     /// Set hosts concurrency
     pub fn set_hosts_concurrency(&mut self, concurrency: usize){
         self.hosts_concurrency = concurrency;
@@ -329,45 +656,78 @@ impl PortScanner {
         self.scan_result.clone()
     }
     /// Get progress receiver
-    pub fn get_progress_receiver(&self) -> Arc<Mutex<Receiver<SocketAddr>>> {
+    /// 
+    /// Delivers ScanEvent from Started to Finished while the scan is running
+    pub fn get_progress_receiver(&self) -> Arc<Mutex<Receiver<ScanEvent>>> {
         self.rx.clone()
     }
     /// Run Port Scan
-    pub async fn run_scan(&mut self){
+    /// 
+    /// Fails if the socket or datalink channel cannot be opened, e.g. without privileges.
+    pub async fn run_scan(&mut self) -> Result<(), Error> {
+        let (destinations, target_stream) = match &self.target_stream {
+            Some(target_stream) => (vec![], Some(TargetStream::new(self.destinations.clone().into_iter().chain(target_stream.clone())))),
+            None => (self.destinations.clone(), None),
+        };
         let mut ip_set: HashSet<IpAddr> = HashSet::new();
-        for dst in self.destinations.clone() {
+        for dst in destinations.clone() {
             ip_set.insert(dst.dst_ip);
         }
+        let technique: ScanTechnique = privilege::get_scan_technique(&self.scan_type, self.src_ip, self.fallback_policy)?;
         let scan_setting: ScanSetting = ScanSetting {
             if_index: self.if_index.clone(),
             src_mac: pnet_datalink::MacAddr::from(self.src_mac),
             dst_mac: pnet_datalink::MacAddr::from(self.dst_mac),
             src_ip: self.src_ip.clone(),
             src_port: self.src_port.clone(),
-            destinations: self.destinations.clone(),
+            destinations: destinations,
             ip_set: ip_set,
+            target_stream: target_stream,
+            cookie_key: CookieKey::new(),
             timeout: self.timeout.clone(),
             wait_time: self.wait_time.clone(),
-            send_rate: self.timeout.clone(),
-            scan_type: self.scan_type.clone(),
+            send_rate: self.send_rate.clone(),
+            rate_controller: self.dynamic_send_rate.map(|dynamic_send_rate| Arc::new(Mutex::new(RateController::new(dynamic_send_rate.min_pps, dynamic_send_rate.max_pps)))),
+            rate_limiter: self.rate_limit.map(|rate_limit| Arc::new(Mutex::new(rate_limit.into_token_bucket()))),
+            adaptive_timing: self.adaptive_timing,
+            retry_count: self.retry_count,
+            retransmission: false,
+            random_seed: if self.randomize { Some(self.random_seed.unwrap_or(rand::random::<u64>())) } else { None },
+            scan_type: technique.scan_type.clone(),
+            privileged: technique.privileged,
+            echo_identifier: None,
+            udp_payloads: self.udp_payloads.clone(),
+            cancel_token: self.cancel_token.clone(),
             hosts_concurrency: self.hosts_concurrency,
             ports_concurrency: self.ports_concurrency,
         };
+        result::send_event(&self.tx, ScanEvent::Started { total: scan_setting.get_probe_count() });
         let start_time = Instant::now();
-        let mut result: PortScanResult = scan_ports(scan_setting, &self.tx).await;
+        let mut result: PortScanResult = match scan_ports(scan_setting, &self.tx).await {
+            Ok(result) => result,
+            Err(e) => {
+                self.scan_result = PortScanResult::new();
+                self.scan_result.scan_status = ScanStatus::Error;
+                result::send_event(&self.tx, ScanEvent::Finished { status: ScanStatus::Error });
+                return Err(e);
+            },
+        };
         result.scan_time = Instant::now().duration_since(start_time);
-        if result.scan_status != ScanStatus::Error {
-            if result.scan_time > self.timeout {
-                result.scan_status = ScanStatus::Timeout;
-            } else {
-                result.scan_status = ScanStatus::Done;
-            }
+        if self.cancel_token.is_cancelled() {
+            result.scan_status = ScanStatus::Cancelled;
+        } else if result.scan_time > self.timeout {
+            result.scan_status = ScanStatus::Timeout;
+        } else {
+            result.scan_status = ScanStatus::Done;
         }
+        result.technique = Some(technique);
+        result::send_event(&self.tx, ScanEvent::Finished { status: result.scan_status.clone() });
         self.scan_result = result;
+        Ok(())
     }
     /// Run scan and return result
-    pub async fn scan(&mut self) -> PortScanResult {
-        self.run_scan().await;
-        self.scan_result.clone()
+    pub async fn scan(&mut self) -> Result<PortScanResult, Error> {
+        self.run_scan().await?;
+        Ok(self.scan_result.clone())
     }
 }
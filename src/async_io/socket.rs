@@ -1,7 +1,11 @@
 use std::io;
+#[cfg(not(target_os="windows"))]
+use std::io::Read;
 use std::mem::MaybeUninit;
 use std::sync::Arc;
 use std::net::IpAddr;
+#[cfg(not(target_os="windows"))]
+use std::net::SocketAddr;
 use async_io::Async;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 
@@ -21,6 +25,30 @@ impl AsyncSocket {
             inner: Arc::new(Async::new(socket)?),
         })
     }
+    /// Unprivileged ICMP echo socket (SOCK_DGRAM + IPPROTO_ICMP)
+    /// 
+    /// Allowed on Linux for the groups in net.ipv4.ping_group_range. 
+    /// Bound to the source address, so the kernel assigns the echo identifier.
+    #[cfg(not(target_os="windows"))]
+    pub fn new_icmp_datagram(addr: IpAddr) -> io::Result<AsyncSocket> {
+        let socket = match addr {
+            IpAddr::V4(_) => Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))?,
+            IpAddr::V6(_) => Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::ICMPV6))?,
+        };
+        socket.bind(&SockAddr::from(SocketAddr::new(addr, 0)))?;
+        socket.set_nonblocking(true)?;
+        Ok(AsyncSocket {
+            inner: Arc::new(Async::new(socket)?),
+        })
+    }
+    /// Echo identifier of the datagram ICMP socket. The kernel rewrites the identifier of the requests to the local port.
+    #[cfg(not(target_os="windows"))]
+    pub fn get_echo_identifier(&self) -> io::Result<u16> {
+        match self.inner.get_ref().local_addr()?.as_socket() {
+            Some(socket_addr) => Ok(socket_addr.port()),
+            None => Err(io::Error::other("Socket is not bound to an IP address")),
+        }
+    }
     pub async fn send_to(&self, buf: &mut [u8], target: &SockAddr) -> io::Result<usize> {
         loop {
             self.inner.writable().await?;
@@ -30,6 +58,21 @@ impl AsyncSocket {
             }
         }
     }
+    /// Receive a datagram with the source address
+    #[cfg(not(target_os="windows"))]
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SockAddr)> {
+        loop {
+            self.inner.readable().await?;
+            match self.inner.read_with(|inner| {
+                let sock_addr: SockAddr = inner.peek_sender()?;
+                let len: usize = (&*inner).read(buf)?;
+                Ok((len, sock_addr))
+            }).await {
+                Ok(result) => return Ok(result),
+                Err(_) => continue,
+            }
+        }
+    }
     #[allow(dead_code)]
     pub async fn recv(&self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
         loop {
@@ -1,212 +1,421 @@
-use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::Sender;
 use std::thread;
-use std::time::Duration;
+use std::time::{Instant, Duration};
 use std::collections::HashMap;
 use socket2::{Protocol, SockAddr, Type};
 use std::sync::mpsc;
 use pnet_packet::Packet;
+use pnet_packet::ethernet::EtherTypes;
+use pnet_packet::ip::IpNextHeaderProtocols;
+use pnet_datalink::MacAddr;
 use async_io::{Async, Timer};
 use futures_lite::{future::FutureExt, io};
 use futures::stream::{self, StreamExt};
 use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
-use crate::result::{HostScanResult, PortScanResult, PortStatus, PortInfo, ScanResult, ScanStatus};
+use crate::result::{self, HostScanResult, PortScanResult, PortStatus, HostInfo, PortInfo, ScanResult, ScanStatus, HostError, ScanEvent};
 use crate::setting::{ScanSetting, ScanType, Destination};
 use crate::packet;
 use crate::async_io::receiver;
+use crate::interface;
+use crate::cancel::CANCEL_CHECK_INTERVAL;
+use crate::error::Error;
 use super::socket::AsyncSocket;
 
-async fn build_icmpv4_echo_packet() -> Vec<u8> {
-    let mut buf = vec![0; 16];
+async fn build_icmpv4_echo_packet(identifier: u16, sequence_number: u16) -> Vec<u8> {
+    let mut buf = [0u8; 16];
     let mut icmp_packet = pnet_packet::icmp::echo_request::MutableEchoRequestPacket::new(&mut buf[..]).unwrap();
-    packet::icmp::build_icmp_packet(&mut icmp_packet);
+    packet::icmp::build_icmp_packet(&mut icmp_packet, identifier, sequence_number);
     icmp_packet.packet().to_vec()
 }
 
-async fn build_tcp_syn_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16) -> Vec<u8> {
+async fn build_icmpv6_echo_packet(src_ip: Ipv6Addr, dst_ip: Ipv6Addr, identifier: u16, sequence_number: u16) -> Vec<u8> {
+    let mut buf = [0u8; packet::icmpv6::ICMPV6_ECHO_PACKET_LEN];
+    let mut icmp_packet = pnet_packet::icmpv6::echo_request::MutableEchoRequestPacket::new(&mut buf[..]).unwrap();
+    packet::icmpv6::build_icmpv6_packet(&mut icmp_packet, src_ip, dst_ip, identifier, sequence_number);
+    icmp_packet.packet().to_vec()
+}
+
+async fn build_tcp_syn_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16, sequence: u32, scan_type: &ScanType) -> Vec<u8> {
     let mut vec: Vec<u8> = vec![0; 66];
     let mut tcp_packet = pnet_packet::tcp::MutableTcpPacket::new(&mut vec[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::tcp::build_tcp_packet(&mut tcp_packet, src_ip, src_port, dst_ip, dst_port);
+    packet::tcp::build_tcp_packet(&mut tcp_packet, src_ip, src_port, dst_ip, dst_port, sequence, scan_type);
     tcp_packet.packet().to_vec()
 }
 
-async fn build_udp_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16) -> Vec<u8> {
-    let mut vec: Vec<u8> = vec![0; 66];
-    let mut udp_packet = pnet_packet::udp::MutableUdpPacket::new(&mut vec[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::udp::build_udp_packet(&mut udp_packet, src_ip, src_port, dst_ip, dst_port);
+async fn build_udp_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut vec: Vec<u8> = vec![0; packet::udp::UDP_HEADER_LEN + payload.len()];
+    let mut udp_packet = pnet_packet::udp::MutableUdpPacket::new(&mut vec[..]).unwrap();
+    packet::udp::build_udp_packet(&mut udp_packet, src_ip, src_port, dst_ip, dst_port, payload);
     udp_packet.packet().to_vec()
 }
 
-async fn send_icmp_echo_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
-    let fut_host = stream::iter(scan_setting.destinations.clone()).for_each_concurrent(
-        scan_setting.hosts_concurrency, |dst| {
-            let socket_addr = SocketAddr::new(dst.dst_ip, 0);
+fn build_ndp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: Ipv6Addr) {
+    let multicast_ip: Ipv6Addr = packet::ndp::get_solicited_node_multicast(dst_ip);
+    // Setup Ethernet header
+    let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, packet::ndp::get_multicast_mac(multicast_ip), EtherTypes::Ipv6);
+    match scan_setting.src_ip {
+        IpAddr::V4(_ip) => {},
+        IpAddr::V6(src_ip) => {
+            // Setup IPv6 header
+            let mut ip_header = pnet_packet::ipv6::MutableIpv6Packet::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)]).unwrap();
+            packet::ipv6::build_ipv6_packet(&mut ip_header, src_ip, multicast_ip, IpNextHeaderProtocols::Icmpv6);
+            ip_header.set_payload_length(packet::ndp::NDP_SOL_PACKET_LEN as u16);
+            ip_header.set_hop_limit(255);
+            // Setup Neighbor Solicitation
+            let mut ndp_packet = pnet_packet::icmpv6::ndp::MutableNeighborSolicitPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)..]).unwrap();
+            packet::ndp::build_neighbor_solicit_packet(&mut ndp_packet, scan_setting.src_mac, src_ip, multicast_ip, dst_ip);
+        },
+    }
+}
+
+fn build_arp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: Ipv4Addr) {
+    // Setup Ethernet header
+    let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, MacAddr::broadcast(), EtherTypes::Arp);
+    // Setup ARP header
+    let mut arp_packet = pnet_packet::arp::MutableArpPacket::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..]).unwrap();
+    match scan_setting.src_ip {
+        IpAddr::V4(src_ip) => {
+            packet::arp::build_arp_packet(&mut arp_packet, scan_setting.src_mac, src_ip, dst_ip);
+        },
+        IpAddr::V6(_ip) => {},
+    }
+}
+
+// Pace the concurrent senders with the shared rate controller and rate limit.
+// The fixed send rate does not apply to the concurrent senders.
+// Returns false if the scan was cancelled while waiting for the slot.
+async fn wait_send_slot(scan_setting: &ScanSetting, packet_len: usize) -> bool {
+    let delay: Duration = match scan_setting.rate_controller {
+        Some(_) => scan_setting.get_send_delay(packet_len),
+        None => scan_setting.get_rate_limit_delay(packet_len),
+    };
+    let start_time = Instant::now();
+    while !scan_setting.is_cancelled() {
+        let elapsed: Duration = Instant::now().duration_since(start_time);
+        if elapsed >= delay {
+            return true;
+        }
+        Timer::after((delay - elapsed).min(CANCEL_CHECK_INTERVAL)).await;
+    }
+    false
+}
+
+async fn send_icmp_echo_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    let fut_host = stream::iter(scan_setting.get_host_iter()).for_each_concurrent(
+        scan_setting.hosts_concurrency, |ip_addr| {
+            let socket_addr = SocketAddr::new(ip_addr, 0);
             let sock_addr = SockAddr::from(socket_addr);
             async move {
-                let mut icmp_packet: Vec<u8> = build_icmpv4_echo_packet().await;
+                let (identifier, sequence_number) = scan_setting.get_echo_id(ip_addr);
+                let mut icmp_packet: Vec<u8> = match (scan_setting.src_ip, ip_addr) {
+                    (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => build_icmpv6_echo_packet(src_ip, dst_ip, identifier, sequence_number).await,
+                    _ => build_icmpv4_echo_packet(identifier, sequence_number).await,
+                };
+                if !wait_send_slot(scan_setting, packet::get_raw_frame_len(ip_addr, icmp_packet.len())).await {
+                    return;
+                }
+                scan_setting.add_echo_request(&scan_result, ip_addr, identifier, sequence_number);
                 match socket.send_to(&mut icmp_packet, &sock_addr).await {
                     Ok(_) => {},
                     Err(_) => {},
                 }
-                match ptx.lock() {
-                    Ok(lr) => {
-                        match lr.send(socket_addr) {
-                            Ok(_) => {},
-                            Err(_) => {},
-                        }
-                    },
-                    Err(_) => {},
-                }
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
             }
         }
     );
     fut_host.await;
 }
 
-async fn send_tcp_syn_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>){
-    let fut_host = stream::iter(scan_setting.destinations.clone()).for_each_concurrent(
-        scan_setting.hosts_concurrency, |dst| {
+async fn send_tcp_syn_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>){
+    // Hosts and ports share a single stream, so randomized order interleaves hosts
+    let fut_probe = stream::iter(scan_setting.get_probe_iter()).for_each_concurrent(
+        scan_setting.hosts_concurrency * scan_setting.ports_concurrency, |socket_addr| {
+            let sock_addr = SockAddr::from(socket_addr);
             async move {
-                let fut_port = stream::iter(dst.dst_ports.clone()).for_each_concurrent(
-                    scan_setting.ports_concurrency, |port| {
-                        let dst = dst.clone();
-                        let socket_addr = SocketAddr::new(dst.dst_ip, port);
-                        let sock_addr = SockAddr::from(socket_addr);
-                        async move {
-                            let mut tcp_packet: Vec<u8> = build_tcp_syn_packet(scan_setting.src_ip, scan_setting.src_port, dst.dst_ip, port).await;
-                            match socket.send_to(&mut tcp_packet, &sock_addr).await {
-                                Ok(_) => {},
-                                Err(_) => {},
-                            }
-                            match ptx.lock() {
-                                Ok(lr) => {
-                                    match lr.send(socket_addr) {
-                                        Ok(_) => {},
-                                        Err(_) => {},
-                                    }
-                                },
-                                Err(_) => {},
-                            }
-                        }
-                    }
-                );
-                fut_port.await;
+                let src_port: u16 = scan_setting.get_src_port(socket_addr.ip(), socket_addr.port());
+                let sequence: u32 = scan_setting.get_tcp_sequence(socket_addr.ip(), socket_addr.port(), src_port);
+                let mut tcp_packet: Vec<u8> = build_tcp_syn_packet(scan_setting.src_ip, src_port, socket_addr.ip(), socket_addr.port(), sequence, &scan_setting.scan_type).await;
+                if !wait_send_slot(scan_setting, packet::get_raw_frame_len(socket_addr.ip(), tcp_packet.len())).await {
+                    return;
+                }
+                scan_setting.add_probe(&scan_result, socket_addr);
+                match socket.send_to(&mut tcp_packet, &sock_addr).await {
+                    Ok(_) => {},
+                    Err(_) => {},
+                }
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
             }
         }
     );
-    fut_host.await;
+    fut_probe.await;
 }
 
-async fn send_udp_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
-    let fut_host = stream::iter(scan_setting.destinations.clone()).for_each_concurrent(
-        scan_setting.hosts_concurrency, |dst| {
+async fn send_udp_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    // Hosts and ports share a single stream, so randomized order interleaves hosts
+    let fut_probe = stream::iter(scan_setting.get_probe_iter()).for_each_concurrent(
+        scan_setting.hosts_concurrency * scan_setting.ports_concurrency, |socket_addr| {
+            let sock_addr = SockAddr::from(socket_addr);
             async move {
-                let fut_port = stream::iter(dst.dst_ports.clone()).for_each_concurrent(
-                    scan_setting.ports_concurrency, |port| {
-                        let dst = dst.clone();
-                        let socket_addr = SocketAddr::new(dst.dst_ip, port);
-                        let sock_addr = SockAddr::from(socket_addr);
-                        async move {
-                            let mut udp_packet: Vec<u8> = build_udp_packet(scan_setting.src_ip, scan_setting.src_port, dst.dst_ip, port).await;
-                            match socket.send_to(&mut udp_packet, &sock_addr).await {
-                                Ok(_) => {},
-                                Err(_) => {},
-                            }
-                            match ptx.lock() {
-                                Ok(lr) => {
-                                    match lr.send(socket_addr) {
-                                        Ok(_) => {},
-                                        Err(_) => {},
-                                    }
-                                },
-                                Err(_) => {},
-                            }
-                        }
-                    }
-                );
-                fut_port.await;
+                let mut udp_packet: Vec<u8> = build_udp_packet(scan_setting.src_ip, scan_setting.get_src_port(socket_addr.ip(), socket_addr.port()), socket_addr.ip(), socket_addr.port(), &scan_setting.udp_payloads.get_payload(socket_addr.port())).await;
+                if !wait_send_slot(scan_setting, packet::get_raw_frame_len(socket_addr.ip(), udp_packet.len())).await {
+                    return;
+                }
+                scan_setting.add_probe(&scan_result, socket_addr);
+                match socket.send_to(&mut udp_packet, &sock_addr).await {
+                    Ok(_) => {},
+                    Err(_) => {},
+                }
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
             }
         }
     );
-    fut_host.await;
+    fut_probe.await;
+}
+
+fn send_ndp_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    for ip_addr in scan_setting.get_host_iter() {
+        let dst_ip: Ipv6Addr = match ip_addr {
+            IpAddr::V4(_) => continue,
+            IpAddr::V6(ip) => ip,
+        };
+        thread::sleep(scan_setting.get_send_delay(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::ndp::NDP_SOL_PACKET_LEN));
+        tx.build_and_send(1, packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::ndp::NDP_SOL_PACKET_LEN, &mut |packet: &mut [u8]| {
+            build_ndp_packet(scan_setting, packet, dst_ip);
+        });
+        let socket_addr = SocketAddr::new(ip_addr, 0);
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+    }
 }
 
-async fn try_connect_ports(concurrency: usize, dst: Destination, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> (IpAddr, Vec<PortInfo>) {
+fn send_arp_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    for ip_addr in scan_setting.get_host_iter() {
+        let dst_ip: Ipv4Addr = match ip_addr {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => continue,
+        };
+        thread::sleep(scan_setting.get_send_delay(packet::ethernet::ETHERNET_HEADER_LEN + packet::arp::ARP_HEADER_LEN));
+        tx.build_and_send(1, packet::ethernet::ETHERNET_HEADER_LEN + packet::arp::ARP_HEADER_LEN, &mut |packet: &mut [u8]| {
+            build_arp_packet(scan_setting, packet, dst_ip);
+        });
+        let socket_addr = SocketAddr::new(ip_addr, 0);
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+    }
+}
+
+async fn try_connect_ports(scan_setting: &ScanSetting, dst: Destination, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> (IpAddr, Vec<PortInfo>, Option<HostError>) {
     let (channel_tx, channel_rx) = mpsc::channel();
-    let conn_timeout = Duration::from_millis(200);
+    let host_error: Mutex<Option<HostError>> = Mutex::new(None);
+    let host_error: &Mutex<Option<HostError>> = &host_error;
     let fut = stream::iter(dst.dst_ports.clone()).for_each_concurrent(
-        concurrency, |port| {
+        scan_setting.ports_concurrency, |port| {
             let dst = dst.clone();
             let channel_tx = channel_tx.clone();
             async move {
                 let socket_addr = SocketAddr::new(dst.dst_ip, port);
+                if !wait_send_slot(scan_setting, packet::get_raw_frame_len(dst.dst_ip, packet::tcp::TCP_HEADER_LEN)).await {
+                    return;
+                }
+                let conn_timeout: Duration = scan_setting.get_conn_timeout(dst.dst_ip, scan_result);
+                let connect_start = Instant::now();
                 let stream = Async::<TcpStream>::connect(socket_addr).or(async {
                     Timer::after(conn_timeout).await;
                     Err(io::ErrorKind::TimedOut.into())
                 }).await;
-                match stream {
+                let port_info: Option<PortInfo> = match stream {
                     Ok(_) => {
-                        let _ = channel_tx.send(port);
+                        let rtt: Duration = Instant::now().duration_since(connect_start);
+                        scan_result.lock().unwrap().add_rtt_sample(dst.dst_ip, rtt);
+                        Some(PortInfo{port: port, status: PortStatus::Open, tcp_window: None, rtt: Some(rtt)})
                     },
-                    _ => {},
-                }
-                match ptx.lock() {
-                    Ok(lr) => {
-                        match lr.send(socket_addr) {
-                            Ok(_) => {},
-                            Err(_) => {},
+                    Err(e) => {
+                        match e.kind() {
+                            io::ErrorKind::ConnectionRefused => {
+                                let rtt: Duration = Instant::now().duration_since(connect_start);
+                                scan_result.lock().unwrap().add_rtt_sample(dst.dst_ip, rtt);
+                                Some(PortInfo{port: port, status: PortStatus::Closed, tcp_window: None, rtt: Some(rtt)})
+                            },
+                            io::ErrorKind::TimedOut => {
+                                Some(PortInfo{port: port, status: PortStatus::Filtered, tcp_window: None, rtt: None})
+                            },
+                            _ => {
+                                match HostError::from_io_error(&e) {
+                                    Some(err) => {
+                                        // Port is still reported, so the result covers every port
+                                        *host_error.lock().unwrap() = Some(err);
+                                        Some(PortInfo{port: port, status: PortStatus::Filtered, tcp_window: None, rtt: None})
+                                    },
+                                    None => None,
+                                }
+                            },
                         }
                     },
-                    Err(_) => {},
+                };
+                result::send_event(ptx, ScanEvent::ProbeSent(socket_addr));
+                match port_info {
+                    Some(port_info) => {
+                        result::send_event(ptx, ScanEvent::PortState(dst.dst_ip, port_info));
+                        let _ = channel_tx.send(port_info);
+                    },
+                    None => {},
                 }
             }
         }
     );
     fut.await;
     drop(channel_tx);
-    let mut open_ports: Vec<PortInfo> = vec![];
+    let mut ports: Vec<PortInfo> = vec![];
     loop {
         match channel_rx.recv() {
-            Ok(port) => {
-                open_ports.push(PortInfo{port: port, status: PortStatus::Open});
+            Ok(port_info) => {
+                ports.push(port_info);
             },
             Err(_) => {
                 break;
             },
         }
     }
-    (dst.dst_ip, open_ports)
+    let host_error: Option<HostError> = *host_error.lock().unwrap();
+    (dst.dst_ip, ports, host_error)
 }
 
-async fn run_connect_scan(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> PortScanResult {
-    let scan_result: Vec<(IpAddr, Vec<PortInfo>)> = stream::iter(scan_setting.destinations.clone().into_iter())
-        .map(|dst| try_connect_ports(scan_setting.ports_concurrency, dst, ptx))
+async fn run_connect_scan(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<PortScanResult, Error> {
+    // RTT estimate per host and of the whole scan for the connection timeout
+    let rtt_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+    let scan_result: Vec<(IpAddr, Vec<PortInfo>, Option<HostError>)> = stream::iter(scan_setting.get_destination_iter())
+        .map(|dst| try_connect_ports(&scan_setting, dst, &rtt_result, ptx))
         .buffer_unordered(scan_setting.hosts_concurrency)
         .collect()
         .await;
     let mut result_map: HashMap<IpAddr, Vec<PortInfo>> = HashMap::new();
-    for (ip, ports) in scan_result {
+    let mut host_errors: HashMap<IpAddr, HostError> = HashMap::new();
+    for (ip, ports, host_error) in scan_result {
         result_map.insert(ip, ports);
+        match host_error {
+            Some(host_error) => {
+                host_errors.insert(ip, host_error);
+            },
+            None => {},
+        }
     }
-    PortScanResult{
+    Ok(PortScanResult{
         result_map: result_map,
+        host_errors: host_errors,
         scan_time: Duration::from_millis(0),
         scan_status: ScanStatus::Ready,
+        technique: None,
+    })
+}
+
+async fn try_connect_ping(scan_setting: &ScanSetting, dst: Destination, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Option<HostInfo> {
+    let ip_addr: IpAddr = dst.dst_ip;
+    let mut host_info: Option<HostInfo> = None;
+    for port in scan_setting.get_ping_ports(&dst) {
+        let socket_addr = SocketAddr::new(ip_addr, port);
+        if !wait_send_slot(scan_setting, packet::get_raw_frame_len(ip_addr, packet::tcp::TCP_HEADER_LEN)).await {
+            break;
+        }
+        let conn_timeout: Duration = scan_setting.get_conn_timeout(ip_addr, scan_result);
+        let connect_start = Instant::now();
+        let stream = Async::<TcpStream>::connect(socket_addr).or(async {
+            Timer::after(conn_timeout).await;
+            Err(io::ErrorKind::TimedOut.into())
+        }).await;
+        // Host is up if the connection is accepted or refused
+        let status: PortStatus = match stream {
+            Ok(_) => PortStatus::Open,
+            Err(e) => {
+                match e.kind() {
+                    io::ErrorKind::ConnectionRefused => PortStatus::Closed,
+                    _ => continue,
+                }
+            },
+        };
+        let rtt: Duration = Instant::now().duration_since(connect_start);
+        scan_result.lock().unwrap().add_rtt_sample(ip_addr, rtt);
+        host_info = Some(HostInfo {
+            ip_addr: ip_addr,
+            ttl: 0,
+            ports: vec![PortInfo{port: port, status: status, tcp_window: None, rtt: Some(rtt)}],
+            mac_addr: None,
+            rtt: Some(rtt),
+        });
+        break;
+    }
+    result::send_event(ptx, ScanEvent::ProbeSent(SocketAddr::new(ip_addr, 0)));
+    match &host_info {
+        Some(host_info) => result::send_event(ptx, ScanEvent::HostUp(host_info.clone())),
+        None => {},
     }
+    host_info
 }
 
-async fn send_ping_packet(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
+async fn run_connect_ping(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    // RTT estimate per host and of the whole scan for the connection timeout
+    let rtt_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+    let hosts: Vec<Option<HostInfo>> = stream::iter(scan_setting.get_destination_iter())
+        .map(|dst| try_connect_ping(&scan_setting, dst, &rtt_result, ptx))
+        .buffer_unordered(scan_setting.hosts_concurrency)
+        .collect()
+        .await;
+    let mut result: HostScanResult = HostScanResult::new();
+    for host_info in hosts {
+        match host_info {
+            Some(host_info) => {
+                result.hosts.push(host_info);
+            },
+            None => {},
+        }
+    }
+    Ok(result)
+}
+
+async fn run_datagram_ping(mut scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    let socket = AsyncSocket::new_icmp_datagram(scan_setting.src_ip)?;
+    // The kernel rewrites the echo identifier to the local port of the socket
+    scan_setting.echo_identifier = Some(socket.get_echo_identifier()?);
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+    let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    let receive_socket: AsyncSocket = socket.clone();
+    let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
+    let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
+    let receive_setting: ScanSetting = scan_setting.clone();
+    let executor = ThreadPool::new()?;
+    // Replies are read from the socket instead of the datalink channel
+    let future = async move {
+        receiver::receive_echo_replies(&receive_socket, receive_setting, &receive_result, &receive_stop).await;
+    };
+    executor.spawn(future).map_err(|e| Error::Io(std::io::Error::other(e)))?;
+    send_icmp_echo_packets(&socket, &scan_setting, &scan_result, ptx).await;
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_icmp_echo_packets(&socket, &retry_setting, &scan_result, ptx).await;
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
+    *stop.lock().unwrap() = true;
+    let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone();
+    return Ok(result);
+}
+
+async fn send_ping_packet(socket: &AsyncSocket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
     match scan_setting.scan_type {
         ScanType::IcmpPingScan => {
-            send_icmp_echo_packets(socket, scan_setting, ptx).await;
+            send_icmp_echo_packets(socket, scan_setting, scan_result, ptx).await;
         },
         ScanType::TcpPingScan => {
-            send_tcp_syn_packets(socket, scan_setting, ptx).await;
+            send_tcp_syn_packets(socket, scan_setting, scan_result, ptx).await;
         },
         ScanType::UdpPingScan => {
-            send_udp_packets(socket, scan_setting, ptx).await;
+            send_udp_packets(socket, scan_setting, scan_result, ptx).await;
         },
         _ => {
             return;
@@ -214,10 +423,13 @@ async fn send_ping_packet(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx:
     }
 }
 
-async fn send_tcp_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
+async fn send_probe_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
     match scan_setting.scan_type {
-        ScanType::TcpSynScan => {
-            send_tcp_syn_packets(socket, scan_setting, ptx).await;
+        ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => {
+            send_tcp_syn_packets(socket, scan_setting, scan_result, ptx).await;
+        },
+        ScanType::UdpScan => {
+            send_udp_packets(socket, scan_setting, scan_result, ptx).await;
         },
         _ => {
             return;
@@ -225,53 +437,104 @@ async fn send_tcp_packets(socket: &AsyncSocket, scan_setting: &ScanSetting, ptx:
     }
 }
 
-pub(crate) async fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> HostScanResult {
+fn send_neighbor_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    match scan_setting.scan_type {
+        ScanType::ArpScan => {
+            send_arp_packets(tx, scan_setting, ptx);
+        },
+        ScanType::NdpScan => {
+            send_ndp_packets(tx, scan_setting, ptx);
+        },
+        _ => {},
+    }
+}
+
+async fn run_neighbor_scan(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    let (mut tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+    let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
+    let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
+    let receive_setting: ScanSetting = scan_setting.clone();
+    let executor = ThreadPool::new()?;
+    let future = async move {
+        receiver::receive_packets(&mut rx, receive_setting, &receive_result, &receive_stop).await;
+    };
+    executor.spawn(future).map_err(|e| Error::Io(std::io::Error::other(e)))?;
+    send_neighbor_packets(&mut tx, &scan_setting, ptx);
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_neighbor_packets(&mut tx, &retry_setting, ptx);
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
+    *stop.lock().unwrap() = true;
+    let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone(); 
+    return Ok(result);
+}
+
+pub(crate) async fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    match scan_setting.scan_type {
+        ScanType::ArpScan | ScanType::NdpScan => {
+            // ARP and NDP replies carry no cookie, so they cannot be matched to streamed targets
+            if scan_setting.is_stateless() {
+                return Err(Error::UnsupportedTargetStream(scan_setting.scan_type.clone()));
+            }
+            return run_neighbor_scan(scan_setting, ptx).await;
+        },
+        ScanType::TcpConnectPingScan => {
+            return run_connect_ping(scan_setting, ptx).await;
+        },
+        ScanType::IcmpPingScan if !scan_setting.privileged => {
+            return run_datagram_ping(scan_setting, ptx).await;
+        },
+        _ => {},
+    }
     let socket = match scan_setting.scan_type {
-        ScanType::IcmpPingScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::ICMPV4).unwrap(),
-        ScanType::TcpPingScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::TCP).unwrap(),
-        ScanType::UdpPingScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::UDP).unwrap(),
+        ScanType::IcmpPingScan => {
+            match scan_setting.src_ip {
+                IpAddr::V4(_) => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::ICMPV4)?,
+                IpAddr::V6(_) => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::ICMPV6)?,
+            }
+        },
+        ScanType::TcpPingScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::TCP)?,
+        ScanType::UdpPingScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::UDP)?,
         _ => {
-            return HostScanResult::new()
+            return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip))
         },
     };
-    let interfaces = pnet_datalink::interfaces();
-    let interface = match interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == scan_setting.if_index).next() {
-        Some(interface) => interface,
-        None => return HostScanResult::new(),
-    };
-    let config = pnet_datalink::Config {
-        write_buffer_size: 4096,
-        read_buffer_size: 4096,
-        read_timeout: None,
-        write_timeout: None,
-        channel_type: pnet_datalink::ChannelType::Layer2,
-        bpf_fd_attempts: 1000,
-        linux_fanout: None,
-        promiscuous: false,
-    };
-    let (mut _tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
-    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+    let (mut _tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
     let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
     let receive_setting: ScanSetting = scan_setting.clone();
-    let executor = ThreadPool::new().unwrap();
+    let executor = ThreadPool::new()?;
     let future = async move {
         receiver::receive_packets(&mut rx, receive_setting, &receive_result, &receive_stop).await;
     };
-    executor.spawn(future).unwrap();
-    send_ping_packet(&socket, &scan_setting, ptx).await;
-    thread::sleep(scan_setting.wait_time);
+    executor.spawn(future).map_err(|e| Error::Io(std::io::Error::other(e)))?;
+    send_ping_packet(&socket, &scan_setting, &scan_result, ptx).await;
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_ping_packet(&socket, &retry_setting, &scan_result, ptx).await;
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
     *stop.lock().unwrap() = true;
     let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone(); 
-    return result;
+    return Ok(result);
 }
 
-pub(crate) async fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> PortScanResult {
+pub(crate) async fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<PortScanResult, Error> {
     match scan_setting.scan_type{
         ScanType::TcpConnectScan => {
             let scan_result = run_connect_scan(scan_setting, ptx).await;
@@ -280,45 +543,47 @@ pub(crate) async fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender
         _ => {},
     }
     let socket = match scan_setting.scan_type {
-        ScanType::TcpSynScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::TCP).unwrap(),
-        ScanType::TcpConnectScan => AsyncSocket::new(scan_setting.src_ip, Type::STREAM, Protocol::TCP).unwrap(),
+        ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::TCP)?,
+        ScanType::TcpConnectScan => AsyncSocket::new(scan_setting.src_ip, Type::STREAM, Protocol::TCP)?,
+        ScanType::UdpScan => AsyncSocket::new(scan_setting.src_ip, Type::RAW, Protocol::UDP)?,
         _ => {
-            return PortScanResult::new()
+            return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip))
         },
     };
-    let interfaces = pnet_datalink::interfaces();
-    let interface = match interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == scan_setting.if_index).next() {
-        Some(interface) => interface,
-        None => return PortScanResult::new(),
-    };
-    let config = pnet_datalink::Config {
-        write_buffer_size: 4096,
-        read_buffer_size: 4096,
-        read_timeout: None,
-        write_timeout: None,
-        channel_type: pnet_datalink::ChannelType::Layer2,
-        bpf_fd_attempts: 1000,
-        linux_fanout: None,
-        promiscuous: false,
-    };
-    let (mut _tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
-    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+    let (mut _tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
     let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
     let receive_setting: ScanSetting = scan_setting.clone();
-    let executor = ThreadPool::new().unwrap();
+    let executor = ThreadPool::new()?;
     let future = async move {
         receiver::receive_packets(&mut rx, receive_setting, &receive_result, &receive_stop).await;
     };
-    executor.spawn(future).unwrap();
-    send_tcp_packets(&socket, &scan_setting, ptx).await;
-    thread::sleep(scan_setting.wait_time);
+    executor.spawn(future).map_err(|e| Error::Io(std::io::Error::other(e)))?;
+    send_probe_packets(&socket, &scan_setting, &scan_result, ptx).await;
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_probe_packets(&socket, &retry_setting, &scan_result, ptx).await;
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
     *stop.lock().unwrap() = true;
+    match scan_setting.scan_type.get_unanswered_port_status() {
+        Some(status) => {
+            if scan_setting.is_cancelled() {
+                // Only the probes sent before cancellation
+                scan_result.lock().unwrap().add_unanswered_probes(status);
+            } else {
+                scan_result.lock().unwrap().add_unanswered_ports(&scan_setting.destinations, status);
+            }
+        },
+        None => {},
+    }
     let result: PortScanResult = scan_result.lock().unwrap().port_scan_result.clone(); 
-    return result;
+    return Ok(result);
 }
@@ -1,9 +1,18 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
 use std::net::{IpAddr, SocketAddr};
+#[cfg(not(target_os="windows"))]
+use std::net::UdpSocket;
 use pnet_packet::Packet;
+use pnet_packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet_packet::icmp::{IcmpTypes, destination_unreachable};
+use pnet_packet::icmpv6::{Icmpv6Types, Icmpv6Code};
+use pnet_packet::icmpv6::ndp::NdpOptionTypes;
+use pnet_packet::tcp::TcpFlags;
+use pnet_packet::arp::ArpOperations;
 use crate::result::{ScanResult, PortInfo, PortStatus, HostInfo};
 use crate::setting::{ScanSetting, ScanType};
+use crate::packet;
 
 pub(crate) fn receive_packets(rx: &mut Box<dyn pnet_datalink::DataLinkReceiver>, scan_setting: ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>) {
     let start_time = Instant::now();
@@ -18,6 +27,9 @@ pub(crate) fn receive_packets(rx: &mut Box<dyn pnet_datalink::DataLinkReceiver>,
                     pnet_packet::ethernet::EtherTypes::Ipv6 => {
                         ipv6_handler(&frame, &scan_setting, &scan_result);
                     },
+                    pnet_packet::ethernet::EtherTypes::Arp => {
+                        arp_handler(&frame, &scan_setting, &scan_result);
+                    },
                     _ => {},
                 }
             },
@@ -32,9 +44,30 @@ pub(crate) fn receive_packets(rx: &mut Box<dyn pnet_datalink::DataLinkReceiver>,
     }
 }
 
+/// Receive echo replies from the datagram ICMP socket. Only replies to the identifier of the socket are delivered.
+#[cfg(not(target_os="windows"))]
+pub(crate) fn receive_echo_replies(socket: &UdpSocket, scan_setting: ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>) {
+    let start_time = Instant::now();
+    let mut buf: Vec<u8> = vec![0; 1500];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, socket_addr)) => {
+                datagram_echo_handler(socket_addr.ip(), &buf[..len], &scan_setting, &scan_result);
+            },
+            Err(_) => {},
+        }
+        if *stop.lock().unwrap(){
+            break;
+        }
+        if Instant::now().duration_since(start_time) > scan_setting.timeout {
+            break;
+        }
+    }
+}
+
 fn ipv4_handler(ethernet: &pnet_packet::ethernet::EthernetPacket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
     if let Some(packet) = pnet_packet::ipv4::Ipv4Packet::new(ethernet.payload()){
-        if scan_setting.ip_set.contains(&IpAddr::V4(packet.get_source())) {
+        if scan_setting.is_target_host(&IpAddr::V4(packet.get_source())) {
             match packet.get_next_level_protocol() {
                 pnet_packet::ip::IpNextHeaderProtocols::Tcp => {
                     tcp_handler_v4(&packet, scan_setting, scan_result);
@@ -47,13 +80,16 @@ fn ipv4_handler(ethernet: &pnet_packet::ethernet::EthernetPacket, scan_setting:
                 }
                 _ => {}
             }
+        }else if packet.get_next_level_protocol() == IpNextHeaderProtocols::Icmp {
+            // ICMP error from a router on the path to the target
+            icmp_handler_v4(&packet, scan_setting, scan_result);
         }
     }
 }
 
 fn ipv6_handler(ethernet: &pnet_packet::ethernet::EthernetPacket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
     if let Some(packet) = pnet_packet::ipv6::Ipv6Packet::new(ethernet.payload()){
-        if scan_setting.ip_set.contains(&IpAddr::V6(packet.get_source())) {
+        if scan_setting.is_target_host(&IpAddr::V6(packet.get_source())) {
             match packet.get_next_header() {
                 pnet_packet::ip::IpNextHeaderProtocols::Tcp => {
                     tcp_handler_v6(&packet, scan_setting, scan_result);
@@ -66,10 +102,39 @@ fn ipv6_handler(ethernet: &pnet_packet::ethernet::EthernetPacket, scan_setting:
                 },
                 _ => {}
             }
+        }else if packet.get_next_header() == IpNextHeaderProtocols::Icmpv6 {
+            // ICMPv6 error from a router on the path to the target
+            icmp_handler_v6(&packet, scan_setting, scan_result);
         }
     }
 }
 
+fn arp_handler(ethernet: &pnet_packet::ethernet::EthernetPacket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    match scan_setting.scan_type {
+        ScanType::ArpScan => {},
+        _ => return,
+    }
+    if let Some(arp_packet) = pnet_packet::arp::ArpPacket::new(ethernet.payload()) {
+        if arp_packet.get_operation() != ArpOperations::Reply {
+            return;
+        }
+        let ip_addr: IpAddr = IpAddr::V4(arp_packet.get_sender_proto_addr());
+        if !scan_setting.is_target_host(&ip_addr) {
+            return;
+        }
+        scan_setting.add_response();
+        scan_result.lock().unwrap().add_host_info(
+            HostInfo {
+                ip_addr: ip_addr,
+                ttl: 0,
+                ports: vec![],
+                mac_addr: Some(arp_packet.get_sender_hw_addr().octets()),
+                rtt: None,
+            }
+        );
+    }
+}
+
 fn tcp_handler_v4(packet: &pnet_packet::ipv4::Ipv4Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
     let tcp_packet = pnet_packet::tcp::TcpPacket::new(packet.payload());
     if let Some(tcp_packet) = tcp_packet {
@@ -77,6 +142,8 @@ fn tcp_handler_v4(packet: &pnet_packet::ipv4::Ipv4Packet, scan_setting: &ScanSet
             ip_addr: IpAddr::V4(packet.get_source()),
             ttl: packet.get_ttl(),
             ports: vec![],
+            mac_addr: None,
+            rtt: None,
         };
         handle_tcp_packet(tcp_packet, host_info, &scan_setting, scan_result);
     }
@@ -89,6 +156,8 @@ fn tcp_handler_v6(packet: &pnet_packet::ipv6::Ipv6Packet, scan_setting: &ScanSet
             ip_addr: IpAddr::V6(packet.get_source()),
             ttl: packet.get_hop_limit(),
             ports: vec![],
+            mac_addr: None,
+            rtt: None,
         };
         handle_tcp_packet(tcp_packet, host_info, &scan_setting, scan_result);
     }
@@ -97,128 +166,376 @@ fn tcp_handler_v6(packet: &pnet_packet::ipv6::Ipv6Packet, scan_setting: &ScanSet
 fn udp_handler_v4(packet: &pnet_packet::ipv4::Ipv4Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
     let udp = pnet_packet::udp::UdpPacket::new(packet.payload());
     if let Some(udp) = udp {
-        handle_udp_packet(udp, &scan_setting, scan_result);
+        let host_info: HostInfo = HostInfo {
+            ip_addr: IpAddr::V4(packet.get_source()),
+            ttl: packet.get_ttl(),
+            ports: vec![],
+            mac_addr: None,
+            rtt: None,
+        };
+        handle_udp_packet(udp, host_info, &scan_setting, scan_result);
     }
 }
 
 fn udp_handler_v6(packet: &pnet_packet::ipv6::Ipv6Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
     let udp = pnet_packet::udp::UdpPacket::new(packet.payload());
     if let Some(udp) = udp {
-        handle_udp_packet(udp, &scan_setting, scan_result);
+        let host_info: HostInfo = HostInfo {
+            ip_addr: IpAddr::V6(packet.get_source()),
+            ttl: packet.get_hop_limit(),
+            ports: vec![],
+            mac_addr: None,
+            rtt: None,
+        };
+        handle_udp_packet(udp, host_info, &scan_setting, scan_result);
     }
 }
 
-fn icmp_handler_v4(packet: &pnet_packet::ipv4::Ipv4Packet, _scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+fn icmp_handler_v4(packet: &pnet_packet::ipv4::Ipv4Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
     let icmp_packet = pnet_packet::icmp::IcmpPacket::new(packet.payload());
-    if let Some(_icmp) = icmp_packet {
-        if !scan_result.lock().unwrap().ip_set.contains(&IpAddr::V4(packet.get_source())) {
-            scan_result.lock().unwrap().host_scan_result.hosts.push(
-                HostInfo {
-                    ip_addr: IpAddr::V4(packet.get_source()),
-                    ttl: packet.get_ttl(),
-                    ports: vec![],
+    if let Some(icmp) = icmp_packet {
+        match scan_setting.scan_type {
+            ScanType::TcpSynScan | ScanType::UdpScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => {
+                if icmp.get_icmp_type() == IcmpTypes::DestinationUnreachable {
+                    handle_icmp_unreachable_v4(&icmp, scan_setting, scan_result);
                 }
-            );
-            scan_result.lock().unwrap().ip_set.insert(IpAddr::V4(packet.get_source()));
-        }
-    }
-}
-
-fn icmp_handler_v6(packet: &pnet_packet::ipv6::Ipv6Packet, _scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
-    let icmp_packet = pnet_packet::icmp::IcmpPacket::new(packet.payload());
-    if let Some(_icmp) = icmp_packet {
-        if !scan_result.lock().unwrap().ip_set.contains(&IpAddr::V6(packet.get_source())) {
-            scan_result.lock().unwrap().host_scan_result.hosts.push(
-                HostInfo {
-                    ip_addr: IpAddr::V6(packet.get_source()),
-                    ttl: packet.get_hop_limit(),
-                    ports: vec![],
+            },
+            ScanType::IcmpPingScan => {
+                if icmp.get_icmp_type() != IcmpTypes::EchoReply {
+                    return;
+                }
+                if let Some(echo_reply) = pnet_packet::icmp::echo_reply::EchoReplyPacket::new(icmp.packet()) {
+                    handle_echo_reply(IpAddr::V4(packet.get_source()), packet.get_ttl(), echo_reply.get_identifier(), echo_reply.get_sequence_number(), scan_setting, scan_result);
+                }
+            },
+            _ => {
+                // Unreachable from the target itself (UDP/TCP ping)
+                if icmp.get_icmp_type() == IcmpTypes::DestinationUnreachable {
+                    handle_ping_unreachable_v4(packet, &icmp, scan_setting, scan_result);
                 }
-            );
-            scan_result.lock().unwrap().ip_set.insert(IpAddr::V6(packet.get_source()));
+            },
         }
     }
 }
 
-fn handle_tcp_packet(tcp_packet: pnet_packet::tcp::TcpPacket, mut host_info: HostInfo, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
-    let socket_addr: SocketAddr = SocketAddr::new(host_info.ip_addr, tcp_packet.get_source());
-    if tcp_packet.get_flags() == pnet_packet::tcp::TcpFlags::SYN | pnet_packet::tcp::TcpFlags::ACK {
-        let port_info = PortInfo{
-            port: socket_addr.port(),
-            status: PortStatus::Open,
-        };
+fn icmp_handler_v6(packet: &pnet_packet::ipv6::Ipv6Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    let icmp_packet = pnet_packet::icmpv6::Icmpv6Packet::new(packet.payload());
+    if let Some(icmp) = icmp_packet {
         match scan_setting.scan_type {
-            ScanType::TcpSynScan => {
-                if !scan_result.lock().unwrap().socket_set.contains(&socket_addr) {
-                    // Avoid deadlock.
-                    let exists: bool = 
-                    if let Some(r) = scan_result.lock().unwrap().port_scan_result.result_map.get_mut(&socket_addr.ip()) {
-                        r.push(port_info);
-                        true
-                    }else {
-                        false
-                    };
-                    if !exists {
-                        scan_result.lock().unwrap().port_scan_result.result_map.insert(socket_addr.ip(), vec![port_info]);
-                    }
-                    scan_result.lock().unwrap().socket_set.insert(socket_addr);
+            ScanType::TcpSynScan | ScanType::UdpScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => {
+                if icmp.get_icmpv6_type() == Icmpv6Types::DestinationUnreachable {
+                    handle_icmp_unreachable_v6(&icmp, scan_setting, scan_result);
                 }
             },
-            _ => {
-                host_info.ports.push(port_info);
-                if !scan_result.lock().unwrap().ip_set.contains(&host_info.ip_addr) {
-                    scan_result.lock().unwrap().host_scan_result.hosts.push(host_info.clone());
-                    match host_info.ip_addr {
-                        IpAddr::V4(ip) => {
-                            scan_result.lock().unwrap().ip_set.insert(IpAddr::V4(ip));
-                        },
-                        IpAddr::V6(ip) => {
-                            scan_result.lock().unwrap().ip_set.insert(IpAddr::V6(ip));
-                        },
-                    }
+            ScanType::NdpScan => {
+                if icmp.get_icmpv6_type() == Icmpv6Types::NeighborAdvert {
+                    handle_neighbor_advert(&icmp, packet.get_hop_limit(), scan_setting, scan_result);
                 }
             },
-        }
-    }else if tcp_packet.get_flags() == pnet_packet::tcp::TcpFlags::RST | pnet_packet::tcp::TcpFlags::ACK {
-        let port_info = PortInfo{
-            port: socket_addr.port(),
-            status: PortStatus::Closed,
-        };
-        match scan_setting.scan_type {
-            ScanType::TcpSynScan => {
-                if !scan_result.lock().unwrap().socket_set.contains(&socket_addr) {
-                    // Avoid deadlock.
-                    let exists: bool = 
-                    if let Some(r) = scan_result.lock().unwrap().port_scan_result.result_map.get_mut(&socket_addr.ip()) {
-                        r.push(port_info);
-                        true
-                    }else {
-                        false
-                    };
-                    if !exists {
-                        scan_result.lock().unwrap().port_scan_result.result_map.insert(socket_addr.ip(), vec![port_info]);
-                    }
-                    scan_result.lock().unwrap().socket_set.insert(socket_addr);    
+            ScanType::IcmpPingScan => {
+                // Only Echo Reply (type 129) answers the ping. Ignore neighbor discovery and router traffic.
+                if icmp.get_icmpv6_type() != Icmpv6Types::EchoReply {
+                    return;
+                }
+                if let Some(echo_reply) = pnet_packet::icmpv6::echo_reply::EchoReplyPacket::new(icmp.packet()) {
+                    handle_echo_reply(IpAddr::V6(packet.get_source()), packet.get_hop_limit(), echo_reply.get_identifier(), echo_reply.get_sequence_number(), scan_setting, scan_result);
                 }
             },
             _ => {
-                host_info.ports.push(port_info);
-                if !scan_result.lock().unwrap().ip_set.contains(&host_info.ip_addr) {
-                    scan_result.lock().unwrap().host_scan_result.hosts.push(host_info.clone());
-                    match host_info.ip_addr {
-                        IpAddr::V4(ip) => {
-                            scan_result.lock().unwrap().ip_set.insert(IpAddr::V4(ip));
-                        },
-                        IpAddr::V6(ip) => {
-                            scan_result.lock().unwrap().ip_set.insert(IpAddr::V6(ip));
-                        },
-                    }
+                // Unreachable from the target itself (UDP/TCP ping)
+                if icmp.get_icmpv6_type() == Icmpv6Types::DestinationUnreachable {
+                    handle_ping_unreachable_v6(packet, &icmp, scan_setting, scan_result);
                 }
             },
         }
     }
 }
 
-fn handle_udp_packet(_udp_packet: pnet_packet::udp::UdpPacket, _scan_setting: &ScanSetting, _scan_result: &Arc<Mutex<ScanResult>>) {
+// Echo reply from the datagram ICMP socket. Linux strips the IP header, some platforms keep the IPv4 header.
+// TTL is not available from the socket.
+#[cfg(not(target_os="windows"))]
+fn datagram_echo_handler(ip_addr: IpAddr, payload: &[u8], scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    match ip_addr {
+        IpAddr::V4(_) => {
+            let icmp_payload: &[u8] = if !payload.is_empty() && payload[0] >> 4 == 4 {
+                let header_len: usize = (payload[0] & 0x0F) as usize * 4;
+                if payload.len() < header_len {
+                    return;
+                }
+                &payload[header_len..]
+            } else {
+                payload
+            };
+            if let Some(echo_reply) = pnet_packet::icmp::echo_reply::EchoReplyPacket::new(icmp_payload) {
+                if echo_reply.get_icmp_type() == IcmpTypes::EchoReply {
+                    handle_echo_reply(ip_addr, 0, echo_reply.get_identifier(), echo_reply.get_sequence_number(), scan_setting, scan_result);
+                }
+            }
+        },
+        IpAddr::V6(_) => {
+            if let Some(echo_reply) = pnet_packet::icmpv6::echo_reply::EchoReplyPacket::new(payload) {
+                if echo_reply.get_icmpv6_type() == Icmpv6Types::EchoReply {
+                    handle_echo_reply(ip_addr, 0, echo_reply.get_identifier(), echo_reply.get_sequence_number(), scan_setting, scan_result);
+                }
+            }
+        },
+    }
+}
+
+// Match the echo reply to the request. By the cookie in stateless mode.
+fn handle_echo_reply(ip_addr: IpAddr, ttl: u8, identifier: u16, sequence_number: u16, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    if scan_setting.is_stateless() {
+        // No send time is kept, so RTT is not measured
+        if scan_setting.get_echo_id(ip_addr) == (identifier, sequence_number) {
+            add_live_host(ip_addr, ttl, None, scan_setting, scan_result);
+        }
+        return;
+    }
+    let rtt: Option<Duration> = scan_result.lock().unwrap().match_echo_reply(ip_addr, identifier, sequence_number);
+    if rtt.is_some() {
+        add_live_host(ip_addr, ttl, rtt, scan_setting, scan_result);
+    }
+}
+
+fn add_live_host(ip_addr: IpAddr, ttl: u8, rtt: Option<Duration>, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    if !scan_setting.is_target_host(&ip_addr) {
+        return;
+    }
+    scan_setting.add_response();
+    scan_result.lock().unwrap().add_host_info(
+        HostInfo {
+            ip_addr: ip_addr,
+            ttl: ttl,
+            ports: vec![],
+            mac_addr: None,
+            rtt: rtt,
+        }
+    );
+}
 
+// Record the advertised target with its link-layer address
+fn handle_neighbor_advert(icmp_packet: &pnet_packet::icmpv6::Icmpv6Packet, hop_limit: u8, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    let advert_packet = match pnet_packet::icmpv6::ndp::NeighborAdvertPacket::new(icmp_packet.packet()) {
+        Some(advert_packet) => advert_packet,
+        None => return,
+    };
+    let ip_addr: IpAddr = IpAddr::V6(advert_packet.get_target_addr());
+    if !scan_setting.is_target_host(&ip_addr) {
+        return;
+    }
+    scan_setting.add_response();
+    if scan_result.lock().unwrap().ip_set.contains(&ip_addr) {
+        return;
+    }
+    let mut mac_addr: Option<[u8; 6]> = None;
+    for option in advert_packet.get_options() {
+        if option.option_type == NdpOptionTypes::TargetLLAddr && option.data.len() >= 6 {
+            let mut octets: [u8; 6] = [0; 6];
+            octets.copy_from_slice(&option.data[..6]);
+            mac_addr = Some(octets);
+        }
+    }
+    scan_result.lock().unwrap().add_host_info(
+        HostInfo {
+            ip_addr: ip_addr,
+            ttl: hop_limit,
+            ports: vec![],
+            mac_addr: mac_addr,
+            rtt: None,
+        }
+    );
+}
+
+// Match ICMP unreachable to the probe by the quoted original datagram
+fn handle_icmp_unreachable_v4(icmp_packet: &pnet_packet::icmp::IcmpPacket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    let unreach_packet = match destination_unreachable::DestinationUnreachablePacket::new(icmp_packet.packet()) {
+        Some(unreach_packet) => unreach_packet,
+        None => return,
+    };
+    if let Some(org_ip_packet) = pnet_packet::ipv4::Ipv4Packet::new(unreach_packet.payload()) {
+        let port_unreachable: bool = unreach_packet.get_icmp_code() == destination_unreachable::IcmpCodes::DestinationPortUnreachable;
+        handle_unreachable_datagram(IpAddr::V4(org_ip_packet.get_destination()), org_ip_packet.get_next_level_protocol(), org_ip_packet.payload(), port_unreachable, scan_setting, scan_result);
+    }
+}
+
+// Match ICMPv6 unreachable to the probe by the quoted original datagram
+fn handle_icmp_unreachable_v6(icmp_packet: &pnet_packet::icmpv6::Icmpv6Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    // Skip 4 bytes of unused field 
+    if icmp_packet.payload().len() < 4 {
+        return;
+    }
+    if let Some(org_ip_packet) = pnet_packet::ipv6::Ipv6Packet::new(&icmp_packet.payload()[4..]) {
+        // Code 4: Port unreachable
+        let port_unreachable: bool = icmp_packet.get_icmpv6_code() == Icmpv6Code::new(4);
+        handle_unreachable_datagram(IpAddr::V6(org_ip_packet.get_destination()), org_ip_packet.get_next_header(), org_ip_packet.payload(), port_unreachable, scan_setting, scan_result);
+    }
+}
+
+// Match ICMP unreachable from the pinged host by the quoted probe. 
+// The cookie in the quoted ports and sequence rules out unreachables for other traffic in stateless mode.
+fn handle_ping_unreachable_v4(packet: &pnet_packet::ipv4::Ipv4Packet, icmp_packet: &pnet_packet::icmp::IcmpPacket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    let unreach_packet = match destination_unreachable::DestinationUnreachablePacket::new(icmp_packet.packet()) {
+        Some(unreach_packet) => unreach_packet,
+        None => return,
+    };
+    if let Some(org_ip_packet) = pnet_packet::ipv4::Ipv4Packet::new(unreach_packet.payload()) {
+        // Routers on the path also send unreachables. Only the target itself proves the host is up.
+        if org_ip_packet.get_destination() != packet.get_source() {
+            return;
+        }
+        let ip_addr: IpAddr = IpAddr::V4(packet.get_source());
+        if is_probe_datagram(ip_addr, org_ip_packet.get_next_level_protocol(), org_ip_packet.payload(), scan_setting) {
+            add_live_host(ip_addr, packet.get_ttl(), None, scan_setting, scan_result);
+        }
+    }
+}
+
+// Match ICMPv6 unreachable from the pinged host by the quoted probe
+fn handle_ping_unreachable_v6(packet: &pnet_packet::ipv6::Ipv6Packet, icmp_packet: &pnet_packet::icmpv6::Icmpv6Packet, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    // Skip 4 bytes of unused field 
+    if icmp_packet.payload().len() < 4 {
+        return;
+    }
+    if let Some(org_ip_packet) = pnet_packet::ipv6::Ipv6Packet::new(&icmp_packet.payload()[4..]) {
+        if org_ip_packet.get_destination() != packet.get_source() {
+            return;
+        }
+        let ip_addr: IpAddr = IpAddr::V6(packet.get_source());
+        if is_probe_datagram(ip_addr, org_ip_packet.get_next_header(), org_ip_packet.payload(), scan_setting) {
+            add_live_host(ip_addr, packet.get_hop_limit(), None, scan_setting, scan_result);
+        }
+    }
+}
+
+// Check the quoted original datagram against the probe sent to the destination
+fn is_probe_datagram(dst_ip: IpAddr, protocol: IpNextHeaderProtocol, org_payload: &[u8], scan_setting: &ScanSetting) -> bool {
+    let probe_protocol: IpNextHeaderProtocol = match scan_setting.scan_type {
+        ScanType::UdpScan | ScanType::UdpPingScan => IpNextHeaderProtocols::Udp,
+        _ => IpNextHeaderProtocols::Tcp,
+    };
+    // Only the first 8 bytes of the original datagram are guaranteed. 
+    // Source and destination ports are at the same offset for TCP and UDP.
+    if protocol != probe_protocol || org_payload.len() < 4 || !scan_setting.is_target_host(&dst_ip) {
+        return false;
+    }
+    let src_port: u16 = u16::from_be_bytes([org_payload[0], org_payload[1]]);
+    let dst_port: u16 = u16::from_be_bytes([org_payload[2], org_payload[3]]);
+    if !scan_setting.is_probe_port(dst_ip, dst_port, src_port) {
+        return false;
+    }
+    // Quoted TCP header carries the sequence number of the probe
+    if protocol == IpNextHeaderProtocols::Tcp && org_payload.len() >= 8 {
+        let sequence: u32 = u32::from_be_bytes([org_payload[4], org_payload[5], org_payload[6], org_payload[7]]);
+        if sequence != scan_setting.get_tcp_sequence(dst_ip, dst_port, src_port) {
+            return false;
+        }
+    }
+    true
+}
+
+fn handle_unreachable_datagram(dst_ip: IpAddr, protocol: IpNextHeaderProtocol, org_payload: &[u8], port_unreachable: bool, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    if !is_probe_datagram(dst_ip, protocol, org_payload, scan_setting) {
+        return;
+    }
+    let dst_port: u16 = u16::from_be_bytes([org_payload[2], org_payload[3]]);
+    // Administratively prohibited and other unreachable codes mean the probe was filtered. 
+    // Only port unreachable for UDP comes from the target port itself.
+    let status: PortStatus = match scan_setting.scan_type {
+        ScanType::UdpScan => {
+            if port_unreachable {
+                PortStatus::Closed
+            }else {
+                PortStatus::Filtered
+            }
+        },
+        _ => PortStatus::Filtered,
+    };
+    scan_setting.add_response();
+    let rtt: Option<Duration> = scan_result.lock().unwrap().match_probe(SocketAddr::new(dst_ip, dst_port));
+    let port_info = PortInfo {
+        port: dst_port,
+        status: status,
+        tcp_window: None,
+        rtt: rtt,
+    };
+    scan_result.lock().unwrap().add_port_info(dst_ip, port_info);
+}
+
+fn handle_tcp_packet(tcp_packet: pnet_packet::tcp::TcpPacket, mut host_info: HostInfo, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    if !scan_setting.is_probe_port(host_info.ip_addr, tcp_packet.get_source(), tcp_packet.get_destination()) {
+        return;
+    }
+    // Discard replies that do not carry the cookie of the probe. Unsolicited or spoofed.
+    let sequence: u32 = scan_setting.get_tcp_sequence(host_info.ip_addr, tcp_packet.get_source(), tcp_packet.get_destination());
+    if !packet::tcp::is_probe_reply(&tcp_packet, sequence, &scan_setting.scan_type) {
+        return;
+    }
+    let syn_ack: bool = tcp_packet.get_flags() == TcpFlags::SYN | TcpFlags::ACK;
+    let rst_ack: bool = tcp_packet.get_flags() == TcpFlags::RST | TcpFlags::ACK;
+    let rst: bool = tcp_packet.get_flags() & TcpFlags::RST == TcpFlags::RST;
+    let status: PortStatus = match scan_setting.scan_type {
+        ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpMaimonScan => {
+            if rst { PortStatus::Closed } else { return }
+        },
+        ScanType::TcpAckScan => {
+            if rst { PortStatus::Unfiltered } else { return }
+        },
+        ScanType::TcpWindowScan => {
+            if !rst {
+                return;
+            }
+            if tcp_packet.get_window() > 0 { PortStatus::Open } else { PortStatus::Closed }
+        },
+        _ => {
+            if syn_ack { PortStatus::Open } else if rst_ack { PortStatus::Closed } else { return }
+        },
+    };
+    scan_setting.add_response();
+    let rtt: Option<Duration> = scan_result.lock().unwrap().match_probe(SocketAddr::new(host_info.ip_addr, tcp_packet.get_source()));
+    let port_info = PortInfo {
+        port: tcp_packet.get_source(),
+        status: status,
+        tcp_window: Some(tcp_packet.get_window()),
+        rtt: rtt,
+    };
+    match scan_setting.scan_type {
+        ScanType::TcpPingScan => {
+            host_info.rtt = rtt;
+            host_info.ports.push(port_info);
+            scan_result.lock().unwrap().add_host_info(host_info);
+        },
+        _ => {
+            scan_result.lock().unwrap().add_port_info(host_info.ip_addr, port_info);
+        },
+    }
+}
+
+fn handle_udp_packet(udp_packet: pnet_packet::udp::UdpPacket, mut host_info: HostInfo, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>) {
+    // Stray datagrams during other scans must not consume the RTT of a probe or count as responses
+    match scan_setting.scan_type {
+        ScanType::UdpScan | ScanType::UdpPingScan => {},
+        _ => return,
+    }
+    if !scan_setting.is_probe_port(host_info.ip_addr, udp_packet.get_source(), udp_packet.get_destination()) {
+        return;
+    }
+    scan_setting.add_response();
+    let rtt: Option<Duration> = scan_result.lock().unwrap().match_probe(SocketAddr::new(host_info.ip_addr, udp_packet.get_source()));
+    let port_info = PortInfo {
+        port: udp_packet.get_source(),
+        status: PortStatus::Open,
+        tcp_window: None,
+        rtt: rtt,
+    };
+    match scan_setting.scan_type {
+        ScanType::UdpScan => {
+            scan_result.lock().unwrap().add_port_info(host_info.ip_addr, port_info);
+        },
+        ScanType::UdpPingScan => {
+            host_info.rtt = rtt;
+            host_info.ports.push(port_info);
+            scan_result.lock().unwrap().add_host_info(host_info);
+        },
+        _ => {},
+    }
 }
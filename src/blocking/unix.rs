@@ -1,155 +1,341 @@
-use std::net::{IpAddr, SocketAddr};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::Sender;
 use std::thread;
 use std::time::{Instant, Duration};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use pnet_packet::Packet;
-use crate::result::{HostScanResult, PortScanResult, ScanResult, PortInfo, PortStatus};
+use pnet_packet::ethernet::EtherTypes;
+use pnet_packet::ip::IpNextHeaderProtocols;
+use pnet_datalink::MacAddr;
+use crate::result::{self, HostScanResult, PortScanResult, ScanResult, HostInfo, PortInfo, PortStatus, HostError, ScanEvent};
 use crate::setting::{ScanSetting};
 use crate::setting::{ScanType};
 use crate::packet;
 use crate::blocking::receiver;
+use crate::interface;
+use crate::error::Error;
 use rayon::prelude::*;
 
-fn build_icmpv4_echo_packet() -> Vec<u8> {
-    let mut buf = vec![0; 16];
+fn build_icmpv4_echo_packet(identifier: u16, sequence_number: u16) -> Vec<u8> {
+    let mut buf = [0u8; 16];
     let mut icmp_packet = pnet_packet::icmp::echo_request::MutableEchoRequestPacket::new(&mut buf[..]).unwrap();
-    packet::icmp::build_icmp_packet(&mut icmp_packet);
+    packet::icmp::build_icmp_packet(&mut icmp_packet, identifier, sequence_number);
     icmp_packet.packet().to_vec()
 }
 
-fn build_tcp_syn_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16) -> Vec<u8> {
+fn build_icmpv6_echo_packet(src_ip: Ipv6Addr, dst_ip: Ipv6Addr, identifier: u16, sequence_number: u16) -> Vec<u8> {
+    let mut buf = [0u8; packet::icmpv6::ICMPV6_ECHO_PACKET_LEN];
+    let mut icmp_packet = pnet_packet::icmpv6::echo_request::MutableEchoRequestPacket::new(&mut buf[..]).unwrap();
+    packet::icmpv6::build_icmpv6_packet(&mut icmp_packet, src_ip, dst_ip, identifier, sequence_number);
+    icmp_packet.packet().to_vec()
+}
+
+fn build_tcp_syn_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16, sequence: u32, scan_type: &ScanType) -> Vec<u8> {
     let mut vec: Vec<u8> = vec![0; 66];
     let mut tcp_packet = pnet_packet::tcp::MutableTcpPacket::new(&mut vec[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::tcp::build_tcp_packet(&mut tcp_packet, src_ip, src_port, dst_ip, dst_port);
+    packet::tcp::build_tcp_packet(&mut tcp_packet, src_ip, src_port, dst_ip, dst_port, sequence, scan_type);
     tcp_packet.packet().to_vec()
 }
 
-fn build_udp_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16) -> Vec<u8> {
-    let mut vec: Vec<u8> = vec![0; 66];
-    let mut udp_packet = pnet_packet::udp::MutableUdpPacket::new(&mut vec[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::udp::build_udp_packet(&mut udp_packet, src_ip, src_port, dst_ip, dst_port);
+fn build_udp_packet(src_ip: IpAddr, src_port: u16, dst_ip: IpAddr, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut vec: Vec<u8> = vec![0; packet::udp::UDP_HEADER_LEN + payload.len()];
+    let mut udp_packet = pnet_packet::udp::MutableUdpPacket::new(&mut vec[..]).unwrap();
+    packet::udp::build_udp_packet(&mut udp_packet, src_ip, src_port, dst_ip, dst_port, payload);
     udp_packet.packet().to_vec()
 }
 
-fn send_icmp_echo_packets(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
-    for dst in scan_setting.destinations.clone() {
-        let socket_addr = SocketAddr::new(dst.dst_ip, 0);
+fn build_ndp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: Ipv6Addr) {
+    let multicast_ip: Ipv6Addr = packet::ndp::get_solicited_node_multicast(dst_ip);
+    // Setup Ethernet header
+    let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, packet::ndp::get_multicast_mac(multicast_ip), EtherTypes::Ipv6);
+    match scan_setting.src_ip {
+        IpAddr::V4(_ip) => {},
+        IpAddr::V6(src_ip) => {
+            // Setup IPv6 header
+            let mut ip_header = pnet_packet::ipv6::MutableIpv6Packet::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)]).unwrap();
+            packet::ipv6::build_ipv6_packet(&mut ip_header, src_ip, multicast_ip, IpNextHeaderProtocols::Icmpv6);
+            ip_header.set_payload_length(packet::ndp::NDP_SOL_PACKET_LEN as u16);
+            ip_header.set_hop_limit(255);
+            // Setup Neighbor Solicitation
+            let mut ndp_packet = pnet_packet::icmpv6::ndp::MutableNeighborSolicitPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)..]).unwrap();
+            packet::ndp::build_neighbor_solicit_packet(&mut ndp_packet, scan_setting.src_mac, src_ip, multicast_ip, dst_ip);
+        },
+    }
+}
+
+fn build_arp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: Ipv4Addr) {
+    // Setup Ethernet header
+    let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, MacAddr::broadcast(), EtherTypes::Arp);
+    // Setup ARP header
+    let mut arp_packet = pnet_packet::arp::MutableArpPacket::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..]).unwrap();
+    match scan_setting.src_ip {
+        IpAddr::V4(src_ip) => {
+            packet::arp::build_arp_packet(&mut arp_packet, scan_setting.src_mac, src_ip, dst_ip);
+        },
+        IpAddr::V6(_ip) => {},
+    }
+}
+
+fn send_icmp_echo_packets(socket: &Socket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    for ip_addr in scan_setting.get_host_iter() {
+        let socket_addr = SocketAddr::new(ip_addr, 0);
         let sock_addr = SockAddr::from(socket_addr);
-        let mut icmp_packet: Vec<u8> = build_icmpv4_echo_packet();
+        let (identifier, sequence_number) = scan_setting.get_echo_id(ip_addr);
+        let mut icmp_packet: Vec<u8> = match (scan_setting.src_ip, ip_addr) {
+            (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => build_icmpv6_echo_packet(src_ip, dst_ip, identifier, sequence_number),
+            _ => build_icmpv4_echo_packet(identifier, sequence_number),
+        };
+        thread::sleep(scan_setting.get_send_delay(packet::get_raw_frame_len(ip_addr, icmp_packet.len())));
+        scan_setting.add_echo_request(&scan_result, ip_addr, identifier, sequence_number);
         match socket.send_to(&mut icmp_packet, &sock_addr) {
             Ok(_) => {},
             Err(_) => {},
         }
-        match ptx.lock() {
-            Ok(lr) => {
-                match lr.send(socket_addr) {
-                    Ok(_) => {},
-                    Err(_) => {},
-                }
-            },
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+    }
+}
+
+fn send_tcp_syn_packets(socket: &Socket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>){
+    for socket_addr in scan_setting.get_probe_iter() {
+        let sock_addr = SockAddr::from(socket_addr);
+        let src_port: u16 = scan_setting.get_src_port(socket_addr.ip(), socket_addr.port());
+        let sequence: u32 = scan_setting.get_tcp_sequence(socket_addr.ip(), socket_addr.port(), src_port);
+        let mut tcp_packet: Vec<u8> = build_tcp_syn_packet(scan_setting.src_ip, src_port, socket_addr.ip(), socket_addr.port(), sequence, &scan_setting.scan_type);
+        thread::sleep(scan_setting.get_send_delay(packet::get_raw_frame_len(socket_addr.ip(), tcp_packet.len())));
+        scan_setting.add_probe(&scan_result, socket_addr);
+        match socket.send_to(&mut tcp_packet, &sock_addr) {
+            Ok(_) => {},
             Err(_) => {},
         }
-        thread::sleep(scan_setting.send_rate);
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
     }
 }
 
-fn send_tcp_syn_packets(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>){
-    for dst in scan_setting.destinations.clone() {
-        for port in dst.dst_ports {
-            let socket_addr = SocketAddr::new(dst.dst_ip, port);
-            let sock_addr = SockAddr::from(socket_addr);
-            let mut tcp_packet: Vec<u8> = build_tcp_syn_packet(scan_setting.src_ip, scan_setting.src_port, dst.dst_ip, port);
-            match socket.send_to(&mut tcp_packet, &sock_addr) {
-                Ok(_) => {},
-                Err(_) => {},
-            }
-            match ptx.lock() {
-                Ok(lr) => {
-                    match lr.send(socket_addr) {
-                        Ok(_) => {},
-                        Err(_) => {},
-                    }
-                },
-                Err(_) => {},
-            }
-            thread::sleep(scan_setting.send_rate);
+fn send_udp_packets(socket: &Socket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    for socket_addr in scan_setting.get_probe_iter() {
+        let sock_addr = SockAddr::from(socket_addr);
+        let mut udp_packet: Vec<u8> = build_udp_packet(scan_setting.src_ip, scan_setting.get_src_port(socket_addr.ip(), socket_addr.port()), socket_addr.ip(), socket_addr.port(), &scan_setting.udp_payloads.get_payload(socket_addr.port()));
+        thread::sleep(scan_setting.get_send_delay(packet::get_raw_frame_len(socket_addr.ip(), udp_packet.len())));
+        scan_setting.add_probe(&scan_result, socket_addr);
+        match socket.send_to(&mut udp_packet, &sock_addr) {
+            Ok(_) => {},
+            Err(_) => {},
         }
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
     }
 }
 
-fn send_udp_packets(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
-    for dst in scan_setting.destinations.clone() {
-        for port in dst.dst_ports {
-            let socket_addr = SocketAddr::new(dst.dst_ip, port);
-            let sock_addr = SockAddr::from(socket_addr);
-            let mut udp_packet: Vec<u8> = build_udp_packet(scan_setting.src_ip, scan_setting.src_port, dst.dst_ip, port);
-            match socket.send_to(&mut udp_packet, &sock_addr) {
-                Ok(_) => {},
-                Err(_) => {},
-            }
-            match ptx.lock() {
-                Ok(lr) => {
-                    match lr.send(socket_addr) {
-                        Ok(_) => {},
-                        Err(_) => {},
-                    }
-                },
-                Err(_) => {},
-            }
-            thread::sleep(scan_setting.send_rate);
-        }
+fn send_ndp_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    for ip_addr in scan_setting.get_host_iter() {
+        let dst_ip: Ipv6Addr = match ip_addr {
+            IpAddr::V4(_) => continue,
+            IpAddr::V6(ip) => ip,
+        };
+        thread::sleep(scan_setting.get_send_delay(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::ndp::NDP_SOL_PACKET_LEN));
+        tx.build_and_send(1, packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::ndp::NDP_SOL_PACKET_LEN, &mut |packet: &mut [u8]| {
+            build_ndp_packet(scan_setting, packet, dst_ip);
+        });
+        let socket_addr = SocketAddr::new(ip_addr, 0);
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+    }
+}
+
+fn send_arp_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    for ip_addr in scan_setting.get_host_iter() {
+        let dst_ip: Ipv4Addr = match ip_addr {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => continue,
+        };
+        thread::sleep(scan_setting.get_send_delay(packet::ethernet::ETHERNET_HEADER_LEN + packet::arp::ARP_HEADER_LEN));
+        tx.build_and_send(1, packet::ethernet::ETHERNET_HEADER_LEN + packet::arp::ARP_HEADER_LEN, &mut |packet: &mut [u8]| {
+            build_arp_packet(scan_setting, packet, dst_ip);
+        });
+        let socket_addr = SocketAddr::new(ip_addr, 0);
+        result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
     }
 }
 
-fn run_connect_scan(scan_setting: ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>) {
+fn run_connect_scan(scan_setting: ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<(), Error> {
     let start_time = Instant::now();
-    let conn_timeout = Duration::from_millis(200);
-    for dst in scan_setting.destinations.clone() {
+    for dst in scan_setting.get_destination_iter() {
         let ip_addr: IpAddr = dst.dst_ip;
         dst.dst_ports.into_par_iter().for_each(|port| {
-            let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).unwrap();
+            if scan_setting.is_cancelled() {
+                return;
+            }
             let socket_addr: SocketAddr = SocketAddr::new(ip_addr, port);
+            // Failure of one socket (e.g. EMFILE) is recorded for the host and the scan continues
+            let socket = match Socket::new(Domain::for_address(socket_addr), Type::STREAM, Some(Protocol::TCP)) {
+                Ok(socket) => socket,
+                Err(_) => {
+                    scan_result.lock().unwrap().port_scan_result.host_errors.insert(ip_addr, HostError::SocketOpen);
+                    return;
+                },
+            };
             let sock_addr = SockAddr::from(socket_addr);
+            let conn_timeout: Duration = scan_setting.get_conn_timeout(ip_addr, scan_result);
+            thread::sleep(scan_setting.get_rate_limit_delay(packet::get_raw_frame_len(ip_addr, packet::tcp::TCP_HEADER_LEN)));
+            result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+            let connect_start = Instant::now();
             match socket.connect_timeout(&sock_addr, conn_timeout) {
                 Ok(_) => {
+                    let rtt: Duration = Instant::now().duration_since(connect_start);
+                    scan_result.lock().unwrap().add_rtt_sample(ip_addr, rtt);
                     let port_info = PortInfo{
                         port: socket_addr.port(),
                         status: PortStatus::Open,
+                        tcp_window: None,
+                        rtt: Some(rtt),
                     };
-                    // Avoid deadlock.
-                    let exists: bool = 
-                    if let Some(r) = scan_result.lock().unwrap().port_scan_result.result_map.get_mut(&socket_addr.ip()) {
-                        r.push(port_info);
-                        true
-                    }else {
-                        false
+                    scan_result.lock().unwrap().add_port_info(ip_addr, port_info);
+                },
+                Err(e) => {
+                    let port_info: PortInfo = match e.kind() {
+                        io::ErrorKind::ConnectionRefused => {
+                            let rtt: Duration = Instant::now().duration_since(connect_start);
+                            scan_result.lock().unwrap().add_rtt_sample(ip_addr, rtt);
+                            PortInfo{
+                                port: socket_addr.port(),
+                                status: PortStatus::Closed,
+                                tcp_window: None,
+                                rtt: Some(rtt),
+                            }
+                        },
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                            PortInfo{
+                                port: socket_addr.port(),
+                                status: PortStatus::Filtered,
+                                tcp_window: None,
+                                rtt: None,
+                            }
+                        },
+                        _ => {
+                            match HostError::from_io_error(&e) {
+                                Some(host_error) => {
+                                    // Port is still reported, so the result covers every port
+                                    scan_result.lock().unwrap().port_scan_result.host_errors.insert(ip_addr, host_error);
+                                    PortInfo{
+                                        port: socket_addr.port(),
+                                        status: PortStatus::Filtered,
+                                        tcp_window: None,
+                                        rtt: None,
+                                    }
+                                },
+                                None => return,
+                            }
+                        },
                     };
-                    if !exists {
-                        scan_result.lock().unwrap().port_scan_result.result_map.insert(socket_addr.ip(), vec![port_info]);
-                    }
+                    scan_result.lock().unwrap().add_port_info(ip_addr, port_info);
                 },
-                Err(_) => {},
             }
             if Instant::now().duration_since(start_time) > scan_setting.timeout {
                 *stop.lock().unwrap() = true;
-                return;
             }
         });
     }
+    Ok(())
+}
+
+fn run_connect_ping(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+    let start_time = Instant::now();
+    scan_setting.get_destination_iter().par_bridge().try_for_each(|dst| -> Result<(), Error> {
+        if Instant::now().duration_since(start_time) > scan_setting.timeout {
+            return Ok(());
+        }
+        let ip_addr: IpAddr = dst.dst_ip;
+        for port in scan_setting.get_ping_ports(&dst) {
+            let socket_addr: SocketAddr = SocketAddr::new(ip_addr, port);
+            let socket = Socket::new(Domain::for_address(socket_addr), Type::STREAM, Some(Protocol::TCP))?;
+            let sock_addr = SockAddr::from(socket_addr);
+            let conn_timeout: Duration = scan_setting.get_conn_timeout(ip_addr, &scan_result);
+            thread::sleep(scan_setting.get_rate_limit_delay(packet::get_raw_frame_len(ip_addr, packet::tcp::TCP_HEADER_LEN)));
+            let connect_start = Instant::now();
+            // Host is up if the connection is accepted or refused
+            let status: PortStatus = match socket.connect_timeout(&sock_addr, conn_timeout) {
+                Ok(_) => PortStatus::Open,
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::ConnectionRefused => PortStatus::Closed,
+                        _ => continue,
+                    }
+                },
+            };
+            let rtt: Duration = Instant::now().duration_since(connect_start);
+            let port_info = PortInfo {
+                port: port,
+                status: status,
+                tcp_window: None,
+                rtt: Some(rtt),
+            };
+            let host_info = HostInfo {
+                ip_addr: ip_addr,
+                ttl: 0,
+                ports: vec![port_info],
+                mac_addr: None,
+                rtt: Some(rtt),
+            };
+            let mut result = scan_result.lock().unwrap();
+            result.add_rtt_sample(ip_addr, rtt);
+            result.add_host_info(host_info);
+            break;
+        }
+        result::send_event(ptx, ScanEvent::ProbeSent(SocketAddr::new(ip_addr, 0)));
+        Ok(())
+    })?;
+    let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone();
+    Ok(result)
+}
+
+fn run_datagram_ping(mut scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    let socket = match scan_setting.src_ip {
+        IpAddr::V4(_) => Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))?,
+        IpAddr::V6(_) => Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::ICMPV6))?,
+    };
+    // The kernel rewrites the echo identifier to the local port of the socket
+    socket.bind(&SockAddr::from(SocketAddr::new(scan_setting.src_ip, 0)))?;
+    scan_setting.echo_identifier = socket.local_addr()?.as_socket().map(|socket_addr| socket_addr.port());
+    // Replies are read from the socket instead of the datalink channel
+    let receive_socket: UdpSocket = socket.try_clone()?.into();
+    receive_socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+    let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
+    let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
+    let receive_setting: ScanSetting = scan_setting.clone();
+    thread::spawn(move || {
+        receiver::receive_echo_replies(&receive_socket, receive_setting, &receive_result, &receive_stop);
+    });
+    send_icmp_echo_packets(&socket, &scan_setting, &scan_result, ptx);
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_icmp_echo_packets(&socket, &retry_setting, &scan_result, ptx);
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
+    *stop.lock().unwrap() = true;
+    let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone();
+    return Ok(result);
 }
 
-fn send_ping_packet(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
+fn send_ping_packet(socket: &Socket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
     match scan_setting.scan_type {
         ScanType::IcmpPingScan => {
-            send_icmp_echo_packets(socket, scan_setting, ptx);
+            send_icmp_echo_packets(socket, scan_setting, scan_result, ptx);
         },
         ScanType::TcpPingScan => {
-            send_tcp_syn_packets(socket, scan_setting, ptx);
+            send_tcp_syn_packets(socket, scan_setting, scan_result, ptx);
         },
         ScanType::UdpPingScan => {
-            send_udp_packets(socket, scan_setting, ptx);
+            send_udp_packets(socket, scan_setting, scan_result, ptx);
         },
         _ => {
             return;
@@ -157,10 +343,13 @@ fn send_ping_packet(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex
     }
 }
 
-fn send_tcp_packets(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
+fn send_probe_packets(socket: &Socket, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
     match scan_setting.scan_type {
-        ScanType::TcpSynScan => {
-            send_tcp_syn_packets(socket, scan_setting, ptx);
+        ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => {
+            send_tcp_syn_packets(socket, scan_setting, scan_result, ptx);
+        },
+        ScanType::UdpScan => {
+            send_udp_packets(socket, scan_setting, scan_result, ptx);
         },
         _ => {
             return;
@@ -168,50 +357,85 @@ fn send_tcp_packets(socket: &Socket, scan_setting: &ScanSetting, ptx: &Arc<Mutex
     }
 }
 
-pub(crate) fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> HostScanResult {
+fn send_neighbor_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    match scan_setting.scan_type {
+        ScanType::ArpScan => {
+            send_arp_packets(tx, scan_setting, ptx);
+        },
+        ScanType::NdpScan => {
+            send_ndp_packets(tx, scan_setting, ptx);
+        },
+        _ => {},
+    }
+}
+
+fn run_neighbor_scan(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    let (mut tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+    let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+    let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
+    let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
+    let receive_setting: ScanSetting = scan_setting.clone();
+    thread::spawn(move || {
+        receiver::receive_packets(&mut rx, receive_setting, &receive_result, &receive_stop);    
+    });
+    send_neighbor_packets(&mut tx, &scan_setting, ptx);
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_neighbor_packets(&mut tx, &retry_setting, ptx);
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
+    *stop.lock().unwrap() = true;
+    let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone(); 
+    return Ok(result);
+}
+
+pub(crate) fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    match scan_setting.scan_type {
+        ScanType::ArpScan | ScanType::NdpScan => {
+            // ARP and NDP replies carry no cookie, so they cannot be matched to streamed targets
+            if scan_setting.is_stateless() {
+                return Err(Error::UnsupportedTargetStream(scan_setting.scan_type.clone()));
+            }
+            return run_neighbor_scan(scan_setting, ptx);
+        },
+        ScanType::TcpConnectPingScan => {
+            return run_connect_ping(scan_setting, ptx);
+        },
+        ScanType::IcmpPingScan if !scan_setting.privileged => {
+            return run_datagram_ping(scan_setting, ptx);
+        },
+        _ => {},
+    }
     let socket = match scan_setting.src_ip {
         IpAddr::V4(_) => {
             match scan_setting.scan_type {
-                ScanType::IcmpPingScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).unwrap(),
-                ScanType::TcpPingScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP)).unwrap(),
-                ScanType::UdpPingScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::UDP)).unwrap(),
+                ScanType::IcmpPingScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?,
+                ScanType::TcpPingScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP))?,
+                ScanType::UdpPingScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::UDP))?,
                 _ => {
-                    return HostScanResult::new()
+                    return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip))
                 },
             }
         },
         IpAddr::V6(_) => {
             match scan_setting.scan_type {
-                ScanType::IcmpPingScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6)).unwrap(),
-                ScanType::TcpPingScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::TCP)).unwrap(),
-                ScanType::UdpPingScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::UDP)).unwrap(),
+                ScanType::IcmpPingScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?,
+                ScanType::TcpPingScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::TCP))?,
+                ScanType::UdpPingScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::UDP))?,
                 _ => {
-                    return HostScanResult::new()
+                    return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip))
                 },
             }
         },
     };
-    let interfaces = pnet_datalink::interfaces();
-    let interface = match interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == scan_setting.if_index).next() {
-        Some(interface) => interface,
-        None => return HostScanResult::new(),
-    };
-    let config = pnet_datalink::Config {
-        write_buffer_size: 4096,
-        read_buffer_size: 4096,
-        read_timeout: None,
-        write_timeout: None,
-        channel_type: pnet_datalink::ChannelType::Layer2,
-        bpf_fd_attempts: 1000,
-        linux_fanout: None,
-        promiscuous: false,
-    };
-    let (mut _tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
-    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+    let (mut _tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
     let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
@@ -219,73 +443,90 @@ pub(crate) fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<Socke
     thread::spawn(move || {
         receiver::receive_packets(&mut rx, receive_setting, &receive_result, &receive_stop);    
     });
-    send_ping_packet(&socket, &scan_setting, ptx);
-    thread::sleep(scan_setting.wait_time);
+    send_ping_packet(&socket, &scan_setting, &scan_result, ptx);
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(&scan_result);
+        match scan_setting.get_retry_setting(&scan_result) {
+            Some(retry_setting) => {
+                send_ping_packet(&socket, &retry_setting, &scan_result, ptx);
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(&scan_result);
     *stop.lock().unwrap() = true;
     let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone(); 
-    return result;
+    return Ok(result);
 }
 
-pub(crate) fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> PortScanResult {
+pub(crate) fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<PortScanResult, Error> {
+    match scan_setting.scan_type {
+        ScanType::TcpConnectScan => {
+            let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+            let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+            run_connect_scan(scan_setting, &scan_result, &stop, ptx)?;
+            let result: PortScanResult = scan_result.lock().unwrap().port_scan_result.clone();
+            return Ok(result);
+        },
+        _ => {},
+    }
     let socket = match scan_setting.src_ip {
         IpAddr::V4(_) => {
             match scan_setting.scan_type {
-                ScanType::TcpSynScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP)).unwrap(),
-                ScanType::TcpConnectScan => Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).unwrap(),
+                ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP))?,
+                ScanType::UdpScan => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::UDP))?,
                 _ => {
-                    return PortScanResult::new()
+                    return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip))
                 },
             }
         },
         IpAddr::V6(_) => {
             match scan_setting.scan_type {
-                ScanType::TcpSynScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::TCP)).unwrap(),
-                ScanType::TcpConnectScan => Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP)).unwrap(),
+                ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::TCP))?,
+                ScanType::UdpScan => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::UDP))?,
                 _ => {
-                    return PortScanResult::new()
+                    return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip))
                 },
             }
         },
     };
-    let interfaces = pnet_datalink::interfaces();
-    let interface = match interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == scan_setting.if_index).next() {
-        Some(interface) => interface,
-        None => return PortScanResult::new(),
-    };
-    let config = pnet_datalink::Config {
-        write_buffer_size: 4096,
-        read_buffer_size: 4096,
-        read_timeout: None,
-        write_timeout: None,
-        channel_type: pnet_datalink::ChannelType::Layer2,
-        bpf_fd_attempts: 1000,
-        linux_fanout: None,
-        promiscuous: false,
-    };
-    let (mut _tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
-    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+    let (mut _tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let receive_result: Arc<Mutex<ScanResult>>  = Arc::clone(&scan_result);
     let receive_stop: Arc<Mutex<bool>> = Arc::clone(&stop);
     let receive_setting: ScanSetting = scan_setting.clone();
     match scan_setting.scan_type {
-        ScanType::TcpSynScan => {
+        ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan | ScanType::UdpScan => {
             thread::spawn(move || {
                 receiver::receive_packets(&mut rx, receive_setting, &receive_result, &receive_stop);    
             });
-            send_tcp_packets(&socket, &scan_setting, ptx);
-            thread::sleep(scan_setting.wait_time);
+            send_probe_packets(&socket, &scan_setting, &scan_result, ptx);
+            for _ in 0..scan_setting.retry_count {
+                scan_setting.wait(&scan_result);
+                match scan_setting.get_retry_setting(&scan_result) {
+                    Some(retry_setting) => {
+                        send_probe_packets(&socket, &retry_setting, &scan_result, ptx);
+                    },
+                    None => break,
+                }
+            }
+            scan_setting.wait(&scan_result);
             *stop.lock().unwrap() = true;
-        },
-        ScanType::TcpConnectScan => {
-            run_connect_scan(scan_setting, &receive_result, &receive_stop);
+            match scan_setting.scan_type.get_unanswered_port_status() {
+                Some(status) => {
+                    if scan_setting.is_cancelled() {
+                        // Only the probes sent before cancellation
+                        scan_result.lock().unwrap().add_unanswered_probes(status);
+                    } else {
+                        scan_result.lock().unwrap().add_unanswered_ports(&scan_setting.destinations, status);
+                    }
+                },
+                None => {},
+            }
         },
         _ => {},
     }
     let result: PortScanResult = scan_result.lock().unwrap().port_scan_result.clone(); 
-    return result;
+    return Ok(result);
 }
@@ -1,4 +1,5 @@
-use std::net::{IpAddr, SocketAddr};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::Sender;
 use std::thread;
@@ -6,12 +7,15 @@ use std::time::{Instant, Duration};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use pnet_packet::ethernet::EtherTypes;
 use pnet_packet::ip::IpNextHeaderProtocols;
+use pnet_datalink::MacAddr;
 use rayon::prelude::*;
-use crate::result::{HostScanResult, PortScanResult, ScanResult, PortInfo, PortStatus};
+use crate::result::{self, HostScanResult, PortScanResult, ScanResult, HostInfo, PortInfo, PortStatus, HostError, ScanEvent};
 use crate::setting::{ScanSetting};
 use crate::setting::{ScanType};
 use crate::packet;
 use crate::blocking::receiver;
+use crate::interface;
+use crate::error::Error;
 
 fn build_tcp_syn_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: IpAddr, dst_port: u16){
     // Setup Ethernet header
@@ -32,10 +36,12 @@ fn build_tcp_syn_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_i
     }
     // Setup TCP header
     let mut tcp_header = pnet_packet::tcp::MutableTcpPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::tcp::build_tcp_packet(&mut tcp_header, scan_setting.src_ip, scan_setting.src_port, dst_ip, dst_port);
+    let src_port: u16 = scan_setting.get_src_port(dst_ip, dst_port);
+    let sequence: u32 = scan_setting.get_tcp_sequence(dst_ip, dst_port, src_port);
+    packet::tcp::build_tcp_packet(&mut tcp_header, scan_setting.src_ip, src_port, dst_ip, dst_port, sequence, &scan_setting.scan_type);
 }
 
-fn build_udp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: IpAddr, dst_port: u16){
+fn build_udp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: IpAddr, dst_port: u16, payload: &[u8]){
     // Setup Ethernet header
     let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
     packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, scan_setting.dst_mac, EtherTypes::Ipv4);
@@ -45,7 +51,10 @@ fn build_udp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: I
         IpAddr::V4(src_ip) => {
             match dst_ip {
                 IpAddr::V4(dst_ip) => {
-                    packet::ipv4::build_ipv4_packet(&mut ip_header, src_ip, dst_ip, IpNextHeaderProtocols::Tcp);
+                    packet::ipv4::build_ipv4_packet(&mut ip_header, src_ip, dst_ip, IpNextHeaderProtocols::Udp);
+                    ip_header.set_total_length((packet::ipv4::IPV4_HEADER_LEN + packet::udp::UDP_HEADER_LEN + payload.len()) as u16);
+                    let checksum = pnet_packet::ipv4::checksum(&ip_header.to_immutable());
+                    ip_header.set_checksum(checksum);
                 },
                 IpAddr::V6(_ip) => {},
             }
@@ -54,202 +63,366 @@ fn build_udp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: I
     }
     // Setup UDP header
     let mut udp_header = pnet_packet::udp::MutableUdpPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::udp::build_udp_packet(&mut udp_header, scan_setting.src_ip, scan_setting.src_port, dst_ip, dst_port);
+    packet::udp::build_udp_packet(&mut udp_header, scan_setting.src_ip, scan_setting.get_src_port(dst_ip, dst_port), dst_ip, dst_port, payload);
+}
+
+fn build_icmp_echo_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: IpAddr, identifier: u16, sequence_number: u16) {
+    match (scan_setting.src_ip, dst_ip) {
+        (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => {
+            // Setup Ethernet header
+            let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+            packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, scan_setting.dst_mac, EtherTypes::Ipv6);
+            // Setup IPv6 header
+            let mut ip_header = pnet_packet::ipv6::MutableIpv6Packet::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)]).unwrap();
+            packet::ipv6::build_ipv6_packet(&mut ip_header, src_ip, dst_ip, IpNextHeaderProtocols::Icmpv6);
+            ip_header.set_payload_length(packet::icmpv6::ICMPV6_ECHO_PACKET_LEN as u16);
+            ip_header.set_hop_limit(64);
+            // Setup ICMPv6 header
+            let mut icmp_packet = pnet_packet::icmpv6::echo_request::MutableEchoRequestPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)..]).unwrap();
+            packet::icmpv6::build_icmpv6_packet(&mut icmp_packet, src_ip, dst_ip, identifier, sequence_number);
+        },
+        _ => {
+            // Setup Ethernet header
+            let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+            packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, scan_setting.dst_mac, EtherTypes::Ipv4);
+            // Setup IP header
+            let mut ip_header = pnet_packet::ipv4::MutableIpv4Packet::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)]).unwrap();
+            match scan_setting.src_ip {
+                IpAddr::V4(src_ip) => {
+                    match dst_ip {
+                        IpAddr::V4(dst_ip) => {
+                            packet::ipv4::build_ipv4_packet(&mut ip_header, src_ip, dst_ip, IpNextHeaderProtocols::Icmp);
+                        },
+                        IpAddr::V6(_ip) => {},
+                    }
+                },
+                IpAddr::V6(_ip) => {},
+            }
+            // Setup ICMP header
+            let mut icmp_packet = pnet_packet::icmp::echo_request::MutableEchoRequestPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
+            packet::icmp::build_icmp_packet(&mut icmp_packet, identifier, sequence_number);
+        },
+    }
 }
 
-fn build_icmp_echo_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: IpAddr) {
+fn build_ndp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: Ipv6Addr) {
+    let multicast_ip: Ipv6Addr = packet::ndp::get_solicited_node_multicast(dst_ip);
     // Setup Ethernet header
     let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
-    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, scan_setting.dst_mac, EtherTypes::Ipv4);
-    // Setup IP header
-    let mut ip_header = pnet_packet::ipv4::MutableIpv4Packet::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)]).unwrap();
+    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, packet::ndp::get_multicast_mac(multicast_ip), EtherTypes::Ipv6);
+    match scan_setting.src_ip {
+        IpAddr::V4(_ip) => {},
+        IpAddr::V6(src_ip) => {
+            // Setup IPv6 header
+            let mut ip_header = pnet_packet::ipv6::MutableIpv6Packet::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)]).unwrap();
+            packet::ipv6::build_ipv6_packet(&mut ip_header, src_ip, multicast_ip, IpNextHeaderProtocols::Icmpv6);
+            ip_header.set_payload_length(packet::ndp::NDP_SOL_PACKET_LEN as u16);
+            ip_header.set_hop_limit(255);
+            // Setup Neighbor Solicitation
+            let mut ndp_packet = pnet_packet::icmpv6::ndp::MutableNeighborSolicitPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN)..]).unwrap();
+            packet::ndp::build_neighbor_solicit_packet(&mut ndp_packet, scan_setting.src_mac, src_ip, multicast_ip, dst_ip);
+        },
+    }
+}
+
+fn build_arp_packet(scan_setting: &ScanSetting, tmp_packet: &mut [u8], dst_ip: Ipv4Addr) {
+    // Setup Ethernet header
+    let mut eth_header = pnet_packet::ethernet::MutableEthernetPacket::new(&mut tmp_packet[..packet::ethernet::ETHERNET_HEADER_LEN]).unwrap();
+    packet::ethernet::build_ethernet_packet(&mut eth_header, scan_setting.src_mac, MacAddr::broadcast(), EtherTypes::Arp);
+    // Setup ARP header
+    let mut arp_packet = pnet_packet::arp::MutableArpPacket::new(&mut tmp_packet[packet::ethernet::ETHERNET_HEADER_LEN..]).unwrap();
     match scan_setting.src_ip {
         IpAddr::V4(src_ip) => {
-            match dst_ip {
-                IpAddr::V4(dst_ip) => {
-                    packet::ipv4::build_ipv4_packet(&mut ip_header, src_ip, dst_ip, IpNextHeaderProtocols::Icmp);
-                },
-                IpAddr::V6(_ip) => {},
-            }
+            packet::arp::build_arp_packet(&mut arp_packet, scan_setting.src_mac, src_ip, dst_ip);
         },
         IpAddr::V6(_ip) => {},
     }
-    // Setup ICMP header
-    let mut icmp_packet = pnet_packet::icmp::echo_request::MutableEchoRequestPacket::new(&mut tmp_packet[(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN)..]).unwrap();
-    packet::icmp::build_icmp_packet(&mut icmp_packet);
 }
 
-fn send_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, stop: &Arc<Mutex<bool>>, ptx: &Arc<Mutex<Sender<SocketAddr>>>) {
+fn send_probe_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
     match scan_setting.scan_type {
-        ScanType::TcpSynScan | ScanType::TcpPingScan => {
-            for dst in scan_setting.destinations.clone() {
-                let dst_ip: IpAddr = dst.dst_ip;
-                for port in dst.dst_ports {
-                    tx.build_and_send(1, 66, &mut |packet: &mut [u8]| {
-                        build_tcp_syn_packet(scan_setting, packet, dst_ip, port);
-                    });
-                    let socket_addr = SocketAddr::new(dst.dst_ip, port);
-                    match ptx.lock() {
-                        Ok(lr) => {
-                            match lr.send(socket_addr) {
-                                Ok(_) => {},
-                                Err(_) => {},
-                            }
-                        },
-                        Err(_) => {},
-                    }
-                    thread::sleep(scan_setting.send_rate);
-                }
+        ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan | ScanType::TcpPingScan => {
+            for socket_addr in scan_setting.get_probe_iter() {
+                thread::sleep(scan_setting.get_send_delay(66));
+                scan_setting.add_probe(&scan_result, socket_addr);
+                tx.build_and_send(1, 66, &mut |packet: &mut [u8]| {
+                    build_tcp_syn_packet(scan_setting, packet, socket_addr.ip(), socket_addr.port());
+                });
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
             }
         },
-        ScanType::UdpPingScan => {
-            for dst in scan_setting.destinations.clone() {
-                let dst_ip: IpAddr = dst.dst_ip;
-                for port in dst.dst_ports {
-                    tx.build_and_send(1, 66, &mut |packet: &mut [u8]| {
-                        build_udp_packet(scan_setting, packet, dst_ip, port);
-                    });
-                    let socket_addr = SocketAddr::new(dst.dst_ip, port);
-                    match ptx.lock() {
-                        Ok(lr) => {
-                            match lr.send(socket_addr) {
-                                Ok(_) => {},
-                                Err(_) => {},
-                            }
-                        },
-                        Err(_) => {},
-                    }
-                    thread::sleep(scan_setting.send_rate);
-                }
+        ScanType::UdpPingScan | ScanType::UdpScan => {
+            for socket_addr in scan_setting.get_probe_iter() {
+                let payload: Vec<u8> = scan_setting.udp_payloads.get_payload(socket_addr.port());
+                let packet_len: usize = packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv4::IPV4_HEADER_LEN + packet::udp::UDP_HEADER_LEN + payload.len();
+                thread::sleep(scan_setting.get_send_delay(packet_len));
+                scan_setting.add_probe(&scan_result, socket_addr);
+                tx.build_and_send(1, packet_len, &mut |packet: &mut [u8]| {
+                    build_udp_packet(scan_setting, packet, socket_addr.ip(), socket_addr.port(), &payload);
+                });
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+            }
+        },
+        ScanType::ArpScan => {
+            for ip_addr in scan_setting.get_host_iter() {
+                let dst_ip: Ipv4Addr = match ip_addr {
+                    IpAddr::V4(ip) => ip,
+                    IpAddr::V6(_) => continue,
+                };
+                thread::sleep(scan_setting.get_send_delay(packet::ethernet::ETHERNET_HEADER_LEN + packet::arp::ARP_HEADER_LEN));
+                tx.build_and_send(1, packet::ethernet::ETHERNET_HEADER_LEN + packet::arp::ARP_HEADER_LEN, &mut |packet: &mut [u8]| {
+                    build_arp_packet(scan_setting, packet, dst_ip);
+                });
+                let socket_addr = SocketAddr::new(ip_addr, 0);
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+            }
+        },
+        ScanType::NdpScan => {
+            for ip_addr in scan_setting.get_host_iter() {
+                let dst_ip: Ipv6Addr = match ip_addr {
+                    IpAddr::V4(_) => continue,
+                    IpAddr::V6(ip) => ip,
+                };
+                thread::sleep(scan_setting.get_send_delay(packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::ndp::NDP_SOL_PACKET_LEN));
+                tx.build_and_send(1, packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::ndp::NDP_SOL_PACKET_LEN, &mut |packet: &mut [u8]| {
+                    build_ndp_packet(scan_setting, packet, dst_ip);
+                });
+                let socket_addr = SocketAddr::new(ip_addr, 0);
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
             }
         },
         ScanType::IcmpPingScan => {
-            for dst in scan_setting.destinations.clone() {
-                tx.build_and_send(1, 66, &mut |packet: &mut [u8]| {
-                    build_icmp_echo_packet(scan_setting, packet, dst.dst_ip);
+            for ip_addr in scan_setting.get_host_iter() {
+                let packet_len: usize = match ip_addr {
+                    IpAddr::V4(_) => 66,
+                    IpAddr::V6(_) => packet::ethernet::ETHERNET_HEADER_LEN + packet::ipv6::IPV6_HEADER_LEN + packet::icmpv6::ICMPV6_ECHO_PACKET_LEN,
+                };
+                let (identifier, sequence_number) = scan_setting.get_echo_id(ip_addr);
+                thread::sleep(scan_setting.get_send_delay(packet_len));
+                scan_setting.add_echo_request(&scan_result, ip_addr, identifier, sequence_number);
+                tx.build_and_send(1, packet_len, &mut |packet: &mut [u8]| {
+                    build_icmp_echo_packet(scan_setting, packet, ip_addr, identifier, sequence_number);
                 });
-                let socket_addr = SocketAddr::new(dst.dst_ip, 0);
-                match ptx.lock() {
-                    Ok(lr) => {
-                        match lr.send(socket_addr) {
-                            Ok(_) => {},
-                            Err(_) => {},
-                        }
-                    },
-                    Err(_) => {},
-                }
-                thread::sleep(scan_setting.send_rate);
+                let socket_addr = SocketAddr::new(ip_addr, 0);
+                result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
             }
         },
         _ => {},
     }
-    thread::sleep(scan_setting.wait_time);
+}
+
+fn send_packets(tx: &mut Box<dyn pnet_datalink::DataLinkSender>, scan_setting: &ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) {
+    send_probe_packets(tx, scan_setting, scan_result, ptx);
+    for _ in 0..scan_setting.retry_count {
+        scan_setting.wait(scan_result);
+        match scan_setting.get_retry_setting(scan_result) {
+            Some(retry_setting) => {
+                send_probe_packets(tx, &retry_setting, scan_result, ptx);
+            },
+            None => break,
+        }
+    }
+    scan_setting.wait(scan_result);
     *stop.lock().unwrap() = true;
 }
 
-fn run_connect_scan(scan_setting: ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>) {
+fn run_connect_scan(scan_setting: ScanSetting, scan_result: &Arc<Mutex<ScanResult>>, stop: &Arc<Mutex<bool>>, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<(), Error> {
     let start_time = Instant::now();
-    let conn_timeout = Duration::from_millis(200);
-    for dst in scan_setting.destinations.clone() {
+    for dst in scan_setting.get_destination_iter() {
         let ip_addr: IpAddr = dst.dst_ip;
         dst.dst_ports.into_par_iter().for_each(|port| {
-            let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).unwrap();
+            if scan_setting.is_cancelled() {
+                return;
+            }
             let socket_addr: SocketAddr = SocketAddr::new(ip_addr, port);
+            // Failure of one socket (e.g. EMFILE) is recorded for the host and the scan continues
+            let socket = match Socket::new(Domain::for_address(socket_addr), Type::STREAM, Some(Protocol::TCP)) {
+                Ok(socket) => socket,
+                Err(_) => {
+                    scan_result.lock().unwrap().port_scan_result.host_errors.insert(ip_addr, HostError::SocketOpen);
+                    return;
+                },
+            };
             let sock_addr = SockAddr::from(socket_addr);
+            let conn_timeout: Duration = scan_setting.get_conn_timeout(ip_addr, scan_result);
+            thread::sleep(scan_setting.get_rate_limit_delay(packet::get_raw_frame_len(ip_addr, packet::tcp::TCP_HEADER_LEN)));
+            result::send_event(ptx, scan_setting.get_probe_event(socket_addr));
+            let connect_start = Instant::now();
             match socket.connect_timeout(&sock_addr, conn_timeout) {
                 Ok(_) => {
+                    let rtt: Duration = Instant::now().duration_since(connect_start);
+                    scan_result.lock().unwrap().add_rtt_sample(ip_addr, rtt);
                     let port_info = PortInfo{
                         port: socket_addr.port(),
                         status: PortStatus::Open,
+                        tcp_window: None,
+                        rtt: Some(rtt),
                     };
-                    // Avoid deadlock.
-                    let exists: bool = 
-                    if let Some(r) = scan_result.lock().unwrap().port_scan_result.result_map.get_mut(&socket_addr.ip()) {
-                        r.push(port_info);
-                        true
-                    }else {
-                        false
+                    scan_result.lock().unwrap().add_port_info(ip_addr, port_info);
+                },
+                Err(e) => {
+                    let port_info: PortInfo = match e.kind() {
+                        io::ErrorKind::ConnectionRefused => {
+                            let rtt: Duration = Instant::now().duration_since(connect_start);
+                            scan_result.lock().unwrap().add_rtt_sample(ip_addr, rtt);
+                            PortInfo{
+                                port: socket_addr.port(),
+                                status: PortStatus::Closed,
+                                tcp_window: None,
+                                rtt: Some(rtt),
+                            }
+                        },
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                            PortInfo{
+                                port: socket_addr.port(),
+                                status: PortStatus::Filtered,
+                                tcp_window: None,
+                                rtt: None,
+                            }
+                        },
+                        _ => {
+                            match HostError::from_io_error(&e) {
+                                Some(host_error) => {
+                                    // Port is still reported, so the result covers every port
+                                    scan_result.lock().unwrap().port_scan_result.host_errors.insert(ip_addr, host_error);
+                                    PortInfo{
+                                        port: socket_addr.port(),
+                                        status: PortStatus::Filtered,
+                                        tcp_window: None,
+                                        rtt: None,
+                                    }
+                                },
+                                None => return,
+                            }
+                        },
                     };
-                    if !exists {
-                        scan_result.lock().unwrap().port_scan_result.result_map.insert(socket_addr.ip(), vec![port_info]);
-                    }
+                    scan_result.lock().unwrap().add_port_info(ip_addr, port_info);
                 },
-                Err(_) => {},
             }
             if Instant::now().duration_since(start_time) > scan_setting.timeout {
                 *stop.lock().unwrap() = true;
-                return;
             }
         });
     }
+    Ok(())
 }
 
-pub(crate) fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> HostScanResult {
-    let interfaces = pnet_datalink::interfaces();
-    let interface = match interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == scan_setting.if_index).next() {
-        Some(interface) => interface,
-        None => return HostScanResult::new(),
-    };
-    let config = pnet_datalink::Config {
-        write_buffer_size: 4096,
-        read_buffer_size: 4096,
-        read_timeout: None,
-        write_timeout: None,
-        channel_type: pnet_datalink::ChannelType::Layer2,
-        bpf_fd_attempts: 1000,
-        linux_fanout: None,
-        promiscuous: false,
-    };
-    let (mut tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
-    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+// Routed packets are framed with the MAC address of the default gateway
+fn run_connect_ping(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+    let start_time = Instant::now();
+    scan_setting.get_destination_iter().par_bridge().try_for_each(|dst| -> Result<(), Error> {
+        if Instant::now().duration_since(start_time) > scan_setting.timeout {
+            return Ok(());
+        }
+        let ip_addr: IpAddr = dst.dst_ip;
+        for port in scan_setting.get_ping_ports(&dst) {
+            let socket_addr: SocketAddr = SocketAddr::new(ip_addr, port);
+            let socket = Socket::new(Domain::for_address(socket_addr), Type::STREAM, Some(Protocol::TCP))?;
+            let sock_addr = SockAddr::from(socket_addr);
+            let conn_timeout: Duration = scan_setting.get_conn_timeout(ip_addr, &scan_result);
+            thread::sleep(scan_setting.get_rate_limit_delay(packet::get_raw_frame_len(ip_addr, packet::tcp::TCP_HEADER_LEN)));
+            let connect_start = Instant::now();
+            // Host is up if the connection is accepted or refused
+            let status: PortStatus = match socket.connect_timeout(&sock_addr, conn_timeout) {
+                Ok(_) => PortStatus::Open,
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::ConnectionRefused => PortStatus::Closed,
+                        _ => continue,
+                    }
+                },
+            };
+            let rtt: Duration = Instant::now().duration_since(connect_start);
+            let port_info = PortInfo {
+                port: port,
+                status: status,
+                tcp_window: None,
+                rtt: Some(rtt),
+            };
+            let host_info = HostInfo {
+                ip_addr: ip_addr,
+                ttl: 0,
+                ports: vec![port_info],
+                mac_addr: None,
+                rtt: Some(rtt),
+            };
+            let mut result = scan_result.lock().unwrap();
+            result.add_rtt_sample(ip_addr, rtt);
+            result.add_host_info(host_info);
+            break;
+        }
+        result::send_event(ptx, ScanEvent::ProbeSent(SocketAddr::new(ip_addr, 0)));
+        Ok(())
+    })?;
+    let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone();
+    Ok(result)
+}
+
+fn check_dst_mac(scan_setting: &ScanSetting) -> Result<(), Error> {
+    if scan_setting.dst_mac == MacAddr::zero() {
+        return Err(Error::GatewayResolution(String::from("MAC address of the default gateway is unknown")));
+    }
+    Ok(())
+}
+
+pub(crate) fn scan_hosts(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<HostScanResult, Error> {
+    match scan_setting.scan_type {
+        ScanType::ArpScan | ScanType::NdpScan => {
+            // ARP and NDP replies carry no cookie, so they cannot be matched to streamed targets
+            if scan_setting.is_stateless() {
+                return Err(Error::UnsupportedTargetStream(scan_setting.scan_type.clone()));
+            }
+        },
+        ScanType::TcpConnectPingScan => return run_connect_ping(scan_setting, ptx),
+        _ => check_dst_mac(&scan_setting)?,
+    }
+    let (mut tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let receive_setting: ScanSetting = scan_setting.clone();
-    rayon::join(|| send_packets(&mut tx, &scan_setting, &stop, ptx),
+    rayon::join(|| send_packets(&mut tx, &scan_setting, &scan_result, &stop, ptx),
                 || receiver::receive_packets(&mut rx, receive_setting, &scan_result, &stop)
     );
     let result: HostScanResult = scan_result.lock().unwrap().host_scan_result.clone(); 
-    return result;
+    return Ok(result);
 }
 
-pub(crate) fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<SocketAddr>>>) -> PortScanResult {
-    let interfaces = pnet_datalink::interfaces();
-    let interface = match interfaces.into_iter().filter(|interface: &pnet_datalink::NetworkInterface| interface.index == scan_setting.if_index).next() {
-        Some(interface) => interface,
-        None => return PortScanResult::new(),
-    };
-    let config = pnet_datalink::Config {
-        write_buffer_size: 4096,
-        read_buffer_size: 4096,
-        read_timeout: None,
-        write_timeout: None,
-        channel_type: pnet_datalink::ChannelType::Layer2,
-        bpf_fd_attempts: 1000,
-        linux_fanout: None,
-        promiscuous: false,
-    };
-    let (mut tx, mut rx) = match pnet_datalink::channel(&interface, config) {
-        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unknown channel type"),
-        Err(e) => panic!("Error happened {}", e),
-    };
-    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new()));
+pub(crate) fn scan_ports(scan_setting: ScanSetting, ptx: &Arc<Mutex<Sender<ScanEvent>>>) -> Result<PortScanResult, Error> {
+    match scan_setting.scan_type {
+        ScanType::TcpConnectScan => {
+            let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
+            let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+            run_connect_scan(scan_setting, &scan_result, &stop, ptx)?;
+            let result: PortScanResult = scan_result.lock().unwrap().port_scan_result.clone();
+            return Ok(result);
+        },
+        _ => {},
+    }
+    let (mut tx, mut rx) = interface::open_channel(scan_setting.if_index, scan_setting.src_ip)?;
+    let scan_result: Arc<Mutex<ScanResult>> = Arc::new(Mutex::new(ScanResult::new_with_event_sender(ptx)));
     let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     let receive_setting: ScanSetting = scan_setting.clone();
     match scan_setting.scan_type {
-        ScanType::TcpSynScan => {
-            rayon::join(|| send_packets(&mut tx, &scan_setting, &stop, ptx),
+        ScanType::TcpSynScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan | ScanType::UdpScan => {
+            check_dst_mac(&scan_setting)?;
+            rayon::join(|| send_packets(&mut tx, &scan_setting, &scan_result, &stop, ptx),
                 || receiver::receive_packets(&mut rx, receive_setting, &scan_result, &stop)
             );
+            match scan_setting.scan_type.get_unanswered_port_status() {
+                Some(status) => {
+                    if scan_setting.is_cancelled() {
+                        // Only the probes sent before cancellation
+                        scan_result.lock().unwrap().add_unanswered_probes(status);
+                    } else {
+                        scan_result.lock().unwrap().add_unanswered_ports(&scan_setting.destinations, status);
+                    }
+                },
+                None => {},
+            }
         },
-        ScanType::TcpConnectScan => {
-            run_connect_scan(scan_setting, &scan_result, &stop);
-        },
-        _ => {},
+        _ => return Err(Error::UnsupportedScanType(scan_setting.scan_type.clone(), scan_setting.src_ip)),
     }
     let result: PortScanResult = scan_result.lock().unwrap().port_scan_result.clone(); 
-    return result;
+    return Ok(result);
 }
@@ -0,0 +1,48 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub(crate) const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Handle to cancel a running scan from another thread or task
+///
+/// Clones share the same state. A cancelled token stays cancelled,
+/// so set a new token to the scanner before running the scan again.
+#[derive(Clone, Debug)]
+pub struct CancelToken {
+    cancelled: Arc<Mutex<bool>>,
+}
+
+impl CancelToken {
+    /// Create new CancelToken
+    pub fn new() -> CancelToken {
+        CancelToken {
+            cancelled: Arc::new(Mutex::new(false)),
+        }
+    }
+    /// Cancel the scan. Sending stops and the scan returns the partial result.
+    pub fn cancel(&self) {
+        *self.cancelled.lock().unwrap() = true;
+    }
+    /// Check if the scan has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.lock().unwrap()
+    }
+    /// Sleep for the duration. Returns early if cancelled.
+    pub(crate) fn sleep(&self, duration: Duration) {
+        let start_time = Instant::now();
+        while !self.is_cancelled() {
+            let elapsed: Duration = Instant::now().duration_since(start_time);
+            if elapsed >= duration {
+                break;
+            }
+            thread::sleep((duration - elapsed).min(CANCEL_CHECK_INTERVAL));
+        }
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        CancelToken::new()
+    }
+}
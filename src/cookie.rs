@@ -0,0 +1,35 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::IpAddr;
+
+/// Number of source ports used to carry the cookie in stateless mode
+pub(crate) const COOKIE_PORT_RANGE: u16 = 256;
+
+/// Secret key of the probe cookie
+///
+/// The cookie is a keyed hash of the probed endpoint, so replies can be validated
+/// without keeping any state per probe. A new random key is used for every scan.
+#[derive(Clone, Debug)]
+pub(crate) struct CookieKey {
+    state: RandomState,
+}
+
+impl CookieKey {
+    pub fn new() -> CookieKey {
+        CookieKey {
+            state: RandomState::new(),
+        }
+    }
+    /// Cookie of the probe from the source port to the destination
+    pub fn get_cookie(&self, dst_ip: IpAddr, dst_port: u16, src_port: u16) -> u32 {
+        self.state.hash_one((dst_ip, dst_port, src_port)) as u32
+    }
+    /// Cookie of the probe to the destination, before the source port is chosen
+    pub fn get_port_cookie(&self, dst_ip: IpAddr, dst_port: u16) -> u32 {
+        self.state.hash_one((dst_ip, dst_port)) as u32
+    }
+    /// Cookie of the probe to the destination host
+    pub fn get_host_cookie(&self, dst_ip: IpAddr) -> u32 {
+        self.state.hash_one(dst_ip) as u32
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use crate::setting::ScanType;
+
+/// Error of scanner setup and scan task
+#[derive(Debug)]
+pub enum Error {
+    /// Raw socket or datalink channel requires privileges (root or CAP_NET_RAW)
+    PermissionDenied(io::Error),
+    /// No network interface with the source IP address
+    InterfaceNotFound(IpAddr),
+    /// Failed to open the datalink channel on the interface
+    ChannelOpen(io::Error),
+    /// Scan type is not supported for the address family of the source IP address
+    UnsupportedScanType(ScanType, IpAddr),
+    /// Failed to resolve the MAC address of the default gateway
+    GatewayResolution(String),
+    /// Scan type cannot match replies to streamed targets
+    UnsupportedTargetStream(ScanType),
+    /// Other I/O error
+    Io(io::Error),
+}
+
+impl Error {
+    // Permission error from opening a raw socket or datalink channel
+    pub(crate) fn from_channel_error(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(error),
+            _ => Error::ChannelOpen(error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PermissionDenied(e) => write!(f, "Permission denied. Raw socket requires root or CAP_NET_RAW: {}", e),
+            Error::InterfaceNotFound(ip_addr) => write!(f, "Network interface not found for {}", ip_addr),
+            Error::ChannelOpen(e) => write!(f, "Failed to open datalink channel: {}", e),
+            Error::UnsupportedScanType(scan_type, ip_addr) => write!(f, "{:?} is not supported for {}", scan_type, ip_addr),
+            Error::GatewayResolution(msg) => write!(f, "Failed to resolve default gateway: {}", msg),
+            Error::UnsupportedTargetStream(scan_type) => write!(f, "{:?} is not supported with streamed targets", scan_type),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PermissionDenied(e) | Error::ChannelOpen(e) | Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(error),
+            _ => Error::Io(error),
+        }
+    }
+}
+
+#[cfg(feature = "os")]
+impl From<netscan_os::Error> for Error {
+    fn from(error: netscan_os::Error) -> Error {
+        match error {
+            netscan_os::Error::PermissionDenied(e) => Error::PermissionDenied(e),
+            netscan_os::Error::InterfaceNotFound(ip_addr) => Error::InterfaceNotFound(ip_addr),
+            netscan_os::Error::ChannelOpen(e) => Error::ChannelOpen(e),
+            netscan_os::Error::GatewayResolution(msg) => Error::GatewayResolution(msg),
+            netscan_os::Error::Io(e) => Error::Io(e),
+        }
+    }
+}
@@ -1,5 +1,7 @@
+use std::io;
 use std::net::IpAddr;
 use pnet_datalink::MacAddr;
+use crate::error::Error;
 
 #[allow(dead_code)]
 pub fn get_interface_index_by_ip(ip_addr: IpAddr) -> Option<u32> {
@@ -27,3 +29,30 @@ pub fn get_default_gateway_macaddr() -> [u8; 6] {
 pub fn get_default_gateway_macaddr() -> [u8; 6] {
     MacAddr::zero().octets()
 }
+
+/// Sender and receiver of Layer2 datalink channel
+pub type DatalinkChannel = (Box<dyn pnet_datalink::DataLinkSender>, Box<dyn pnet_datalink::DataLinkReceiver>);
+
+/// Open Layer2 datalink channel on the interface with the index
+pub fn open_channel(if_index: u32, src_ip: IpAddr) -> Result<DatalinkChannel, Error> {
+    let interfaces = pnet_datalink::interfaces();
+    let interface = match interfaces.into_iter().find(|interface: &pnet_datalink::NetworkInterface| interface.index == if_index) {
+        Some(interface) => interface,
+        None => return Err(Error::InterfaceNotFound(src_ip)),
+    };
+    let config = pnet_datalink::Config {
+        write_buffer_size: 4096,
+        read_buffer_size: 4096,
+        read_timeout: None,
+        write_timeout: None,
+        channel_type: pnet_datalink::ChannelType::Layer2,
+        bpf_fd_attempts: 1000,
+        linux_fanout: None,
+        promiscuous: false,
+    };
+    match pnet_datalink::channel(&interface, config) {
+        Ok(pnet_datalink::Channel::Ethernet(tx, rx)) => Ok((tx, rx)),
+        Ok(_) => Err(Error::ChannelOpen(io::Error::new(io::ErrorKind::Unsupported, "Unknown channel type"))),
+        Err(e) => Err(Error::from_channel_error(e)),
+    }
+}
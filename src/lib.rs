@@ -1,10 +1,22 @@
 mod interface;
 mod packet;
+mod timing;
+mod permutation;
+mod cookie;
+mod error;
+mod privilege;
+mod cancel;
 
 pub mod setting;
 pub mod result;
+pub mod target;
+pub mod ports;
 pub mod blocking;
 
+pub use error::Error;
+pub use privilege::has_raw_socket_privilege;
+pub use cancel::CancelToken;
+
 #[cfg(feature = "async")]
 pub mod async_io;
 
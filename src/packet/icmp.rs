@@ -2,10 +2,10 @@ use pnet_packet::Packet;
 use pnet_packet::icmp::echo_request::MutableEchoRequestPacket;
 use pnet_packet::icmp::IcmpTypes;
 
-pub fn build_icmp_packet(icmp_packet:&mut MutableEchoRequestPacket) {
+pub fn build_icmp_packet(icmp_packet:&mut MutableEchoRequestPacket, identifier: u16, sequence_number: u16) {
     icmp_packet.set_icmp_type(IcmpTypes::EchoRequest);
-    icmp_packet.set_sequence_number(rand::random::<u16>());
-    icmp_packet.set_identifier(rand::random::<u16>());
+    icmp_packet.set_sequence_number(sequence_number);
+    icmp_packet.set_identifier(identifier);
     let icmp_check_sum = pnet_packet::util::checksum(&icmp_packet.packet(), 1);
     icmp_packet.set_checksum(icmp_check_sum);
 }
@@ -0,0 +1,16 @@
+use std::net::Ipv6Addr;
+use pnet_packet::Packet;
+use pnet_packet::icmpv6::echo_request::MutableEchoRequestPacket;
+use pnet_packet::icmpv6::{Icmpv6Types, Icmpv6Packet};
+
+// ICMPv6 header(4) + Identifier(2) + Sequence number(2) + Payload(8)
+pub const ICMPV6_ECHO_PACKET_LEN: usize = 16;
+
+pub fn build_icmpv6_packet(icmp_packet: &mut MutableEchoRequestPacket, src_ip: Ipv6Addr, dst_ip: Ipv6Addr, identifier: u16, sequence_number: u16) {
+    icmp_packet.set_icmpv6_type(Icmpv6Types::EchoRequest);
+    icmp_packet.set_sequence_number(sequence_number);
+    icmp_packet.set_identifier(identifier);
+    // Checksum covers the IPv6 pseudo-header
+    let icmp_check_sum = pnet_packet::icmpv6::checksum(&Icmpv6Packet::new(icmp_packet.packet()).unwrap(), &src_ip, &dst_ip);
+    icmp_packet.set_checksum(icmp_check_sum);
+}
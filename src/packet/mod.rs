@@ -1,8 +1,22 @@
 pub mod arp;
 pub mod ethernet;
 pub mod icmp;
+pub mod icmpv6;
 pub mod ipv4;
 pub mod ipv6;
+pub mod ndp;
 pub mod tcp;
 pub mod udp;
 pub mod endpoint;
+pub mod payload;
+
+use std::net::IpAddr;
+
+/// Length on the wire of the packet sent through raw socket. 
+/// Ethernet and IP headers are added by the OS.
+pub fn get_raw_frame_len(ip_addr: IpAddr, packet_len: usize) -> usize {
+    match ip_addr {
+        IpAddr::V4(_) => ethernet::ETHERNET_HEADER_LEN + ipv4::IPV4_HEADER_LEN + packet_len,
+        IpAddr::V6(_) => ethernet::ETHERNET_HEADER_LEN + ipv6::IPV6_HEADER_LEN + packet_len,
+    }
+}
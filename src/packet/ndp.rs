@@ -0,0 +1,33 @@
+use std::net::Ipv6Addr;
+use pnet_packet::Packet;
+use pnet_packet::icmpv6::{Icmpv6Types, Icmpv6Packet};
+use pnet_packet::icmpv6::ndp::{MutableNeighborSolicitPacket, NdpOption, NdpOptionTypes};
+use pnet_datalink::MacAddr;
+
+// ICMPv6 header(4) + Reserved(4) + Target address(16) + Source link-layer address option(8)
+pub const NDP_SOL_PACKET_LEN: usize = 32;
+
+/// Get solicited-node multicast address (ff02::1:ffXX:XXXX) for the target
+pub fn get_solicited_node_multicast(ip_addr: Ipv6Addr) -> Ipv6Addr {
+    let segments = ip_addr.segments();
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00 | (segments[6] & 0x00ff), segments[7])
+}
+
+/// Get Ethernet multicast address (33:33:XX:XX:XX:XX) for the IPv6 multicast address
+pub fn get_multicast_mac(ip_addr: Ipv6Addr) -> MacAddr {
+    let octets = ip_addr.octets();
+    MacAddr::new(0x33, 0x33, octets[12], octets[13], octets[14], octets[15])
+}
+
+pub fn build_neighbor_solicit_packet(ndp_packet: &mut MutableNeighborSolicitPacket, src_mac: MacAddr, src_ip: Ipv6Addr, dst_ip: Ipv6Addr, target_ip: Ipv6Addr) {
+    ndp_packet.set_icmpv6_type(Icmpv6Types::NeighborSolicit);
+    ndp_packet.set_target_addr(target_ip);
+    ndp_packet.set_options(&[NdpOption {
+        option_type: NdpOptionTypes::SourceLLAddr,
+        length: 1,
+        data: src_mac.octets().to_vec(),
+    }]);
+    let icmp_packet = Icmpv6Packet::new(ndp_packet.packet()).unwrap();
+    let icmp_check_sum = pnet_packet::icmpv6::checksum(&icmp_packet, &src_ip, &dst_ip);
+    ndp_packet.set_checksum(icmp_check_sum);
+}
@@ -0,0 +1,27 @@
+// Well-known UDP service probes
+
+/// DNS: version.bind TXT query (CHAOS class)
+pub const DNS_VERSION_BIND: &[u8] = b"\x00\x06\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07version\x04bind\x00\x00\x10\x00\x03";
+
+/// NTP: mode 3 (client) request, version 4
+pub const NTP_REQUEST: &[u8] = b"\xe3\x00\x04\xfa\x00\x01\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+
+/// NetBIOS: NBSTAT name query for "*"
+pub const NETBIOS_NAME_QUERY: &[u8] = b"\x80\xf0\x00\x10\x00\x01\x00\x00\x00\x00\x00\x00\x20CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\x00\x00\x21\x00\x01";
+
+/// SNMP: v1 GetRequest for sysDescr.0 with community "public"
+/// 
+/// Default for port 161. Agents with a v1/v2c community (e.g. net-snmp rocommunity) answer either version.
+pub const SNMP_GET_REQUEST: &[u8] = b"\x30\x26\x02\x01\x00\x04\x06public\xa0\x19\x02\x01\x01\x02\x01\x00\x02\x01\x00\x30\x0e\x30\x0c\x06\x08\x2b\x06\x01\x02\x01\x01\x01\x00\x05\x00";
+
+/// SNMP: v2c GetRequest for sysDescr.0 with community "public", for agents that drop v1
+pub const SNMP_V2C_GET_REQUEST: &[u8] = b"\x30\x26\x02\x01\x01\x04\x06public\xa0\x19\x02\x01\x01\x02\x01\x00\x02\x01\x00\x30\x0e\x30\x0c\x06\x08\x2b\x06\x01\x02\x01\x01\x01\x00\x05\x00";
+
+/// MS-SQL: SQL Server Resolution Protocol ping
+pub const MSSQL_PING: &[u8] = b"\x02";
+
+/// SSDP: M-SEARCH for all devices
+pub const SSDP_M_SEARCH: &[u8] = b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: ssdp:all\r\n\r\n";
+
+/// mDNS: PTR query for _services._dns-sd._udp.local
+pub const MDNS_SERVICES_QUERY: &[u8] = b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x09_services\x07_dns-sd\x04_udp\x05local\x00\x00\x0c\x00\x01";
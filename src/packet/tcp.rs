@@ -1,19 +1,47 @@
 use std::net::IpAddr;
-use pnet_packet::tcp::{MutableTcpPacket, TcpOption, TcpFlags};
+use pnet_packet::tcp::{MutableTcpPacket, TcpPacket, TcpOption, TcpFlags};
+use crate::setting::ScanType;
 
-pub fn build_tcp_packet(tcp_packet:&mut MutableTcpPacket, src_ip: IpAddr, src_port:u16, dst_ip: IpAddr, dst_port:u16) {
+/// Length of the TCP header with the options of the probe
+pub const TCP_HEADER_LEN: usize = 32;
+
+pub fn build_tcp_packet(tcp_packet:&mut MutableTcpPacket, src_ip: IpAddr, src_port:u16, dst_ip: IpAddr, dst_port:u16, sequence: u32, scan_type: &ScanType) {
     tcp_packet.set_source(src_port);
     tcp_packet.set_destination(dst_port);
     tcp_packet.set_window(64240);
     tcp_packet.set_data_offset(8);
     tcp_packet.set_urgent_ptr(0);
-    tcp_packet.set_sequence(0);
+    // Cookie of the probe
+    tcp_packet.set_sequence(sequence);
+    tcp_packet.set_acknowledgement(0);
     tcp_packet.set_options(&[TcpOption::mss(1460)
     , TcpOption::sack_perm()
     , TcpOption::nop()
     , TcpOption::nop()
     , TcpOption::wscale(7)]);
-    tcp_packet.set_flags(TcpFlags::SYN);
+    match scan_type {
+        ScanType::TcpFinScan => {
+            tcp_packet.set_flags(TcpFlags::FIN);
+        },
+        ScanType::TcpNullScan => {
+            tcp_packet.set_flags(0);
+        },
+        ScanType::TcpXmasScan => {
+            tcp_packet.set_flags(TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG);
+        },
+        // Cookie also in the acknowledgement number, which is echoed in the RST sequence number
+        ScanType::TcpAckScan | ScanType::TcpWindowScan => {
+            tcp_packet.set_flags(TcpFlags::ACK);
+            tcp_packet.set_acknowledgement(sequence);
+        },
+        ScanType::TcpMaimonScan => {
+            tcp_packet.set_flags(TcpFlags::FIN | TcpFlags::ACK);
+            tcp_packet.set_acknowledgement(sequence);
+        },
+        _ => {
+            tcp_packet.set_flags(TcpFlags::SYN);
+        },
+    }
     match src_ip {
         IpAddr::V4(src_ip) => {
             match dst_ip {
@@ -35,3 +63,21 @@ pub fn build_tcp_packet(tcp_packet:&mut MutableTcpPacket, src_ip: IpAddr, src_po
         },
     }
 }
+
+/// Check that the reply acknowledges the probe sent with the sequence number
+///
+/// RST to a segment with ACK takes its sequence number from the acknowledgement number.
+/// Otherwise the reply acknowledges the sequence number plus the SYN or FIN flag (RFC 9293 3.10.7).
+pub fn is_probe_reply(tcp_packet: &TcpPacket, sequence: u32, scan_type: &ScanType) -> bool {
+    match scan_type {
+        ScanType::TcpAckScan | ScanType::TcpWindowScan | ScanType::TcpMaimonScan => {
+            tcp_packet.get_sequence() == sequence
+        },
+        ScanType::TcpNullScan => {
+            tcp_packet.get_flags() & TcpFlags::ACK == TcpFlags::ACK && tcp_packet.get_acknowledgement() == sequence
+        },
+        _ => {
+            tcp_packet.get_flags() & TcpFlags::ACK == TcpFlags::ACK && tcp_packet.get_acknowledgement() == sequence.wrapping_add(1)
+        },
+    }
+}
@@ -1,10 +1,13 @@
 use std::net::IpAddr;
 use pnet_packet::udp::MutableUdpPacket;
 
-pub fn build_udp_packet(udp_packet:&mut MutableUdpPacket, src_ip: IpAddr, src_port:u16, dst_ip: IpAddr, dst_port:u16) {
-    udp_packet.set_length(8);
+pub const UDP_HEADER_LEN: usize = 8;
+
+pub fn build_udp_packet(udp_packet:&mut MutableUdpPacket, src_ip: IpAddr, src_port:u16, dst_ip: IpAddr, dst_port:u16, payload: &[u8]) {
+    udp_packet.set_length((UDP_HEADER_LEN + payload.len()) as u16);
     udp_packet.set_source(src_port);
     udp_packet.set_destination(dst_port);
+    udp_packet.set_payload(payload);
     match src_ip {
         IpAddr::V4(src_ip) => {
             match dst_ip {
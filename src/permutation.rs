@@ -0,0 +1,338 @@
+use std::net::{IpAddr, SocketAddr};
+use crate::setting::Destination;
+use crate::target::TargetStream;
+
+fn mix(mut x: u64) -> u64 {
+    // splitmix64 finalizer
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Cyclic permutation of 0..n with constant memory
+///
+/// Full-period linear congruential generator over the next power of two (Hull-Dobell),
+/// scrambled by a bijection on the same range. Values out of range are skipped,
+/// so every index in 0..n is visited exactly once.
+#[derive(Clone, Debug)]
+pub(crate) struct CyclicPermutation {
+    n: u64,
+    mask: u64,
+    shift: u32,
+    multiplier: u64,
+    increment: u64,
+    scramble: u64,
+    current: u64,
+    remaining: u64,
+}
+
+impl CyclicPermutation {
+    pub fn new(n: u64, seed: u64) -> CyclicPermutation {
+        let size: u64 = n.checked_next_power_of_two().unwrap_or(1 << 63).max(4);
+        let mask: u64 = size.wrapping_sub(1);
+        let seed: u64 = mix(seed);
+        CyclicPermutation {
+            n: n,
+            mask: mask,
+            shift: (size.trailing_zeros() / 2).max(1),
+            // a = 1 mod 4 and c odd give the full period
+            multiplier: ((mix(seed) << 2) | 1) & mask,
+            increment: (mix(seed ^ 1) | 1) & mask,
+            scramble: mix(seed ^ 2) | 1,
+            current: mix(seed ^ 3) & mask,
+            remaining: size,
+        }
+    }
+}
+
+impl Iterator for CyclicPermutation {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        while self.remaining > 0 {
+            let mut value: u64 = self.current;
+            self.current = self.current.wrapping_mul(self.multiplier).wrapping_add(self.increment) & self.mask;
+            self.remaining = self.remaining.wrapping_sub(1);
+            value = (value ^ (value >> self.shift)).wrapping_mul(self.scramble) & self.mask;
+            if value < self.n {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Streamed destinations buffered at a time when randomized. Bounds the memory of shuffling the stream.
+pub(crate) const STREAM_WINDOW_SIZE: usize = 4096;
+
+/// Permutation of the probes to the destinations
+///
+/// Permutes over the number of probes. The host of a probe index is found by binary search
+/// over the offsets of the hosts, so uneven port lists do not add skipped indexes.
+struct ProbePermutation {
+    permutation: CyclicPermutation,
+    offsets: Vec<u64>,
+}
+
+impl ProbePermutation {
+    fn new(destinations: &[Destination], seed: u64) -> ProbePermutation {
+        let mut offsets: Vec<u64> = Vec::with_capacity(destinations.len());
+        let mut probe_count: u64 = 0;
+        for dst in destinations {
+            offsets.push(probe_count);
+            probe_count += dst.dst_ports.len() as u64;
+        }
+        ProbePermutation {
+            permutation: CyclicPermutation::new(probe_count, seed),
+            offsets: offsets,
+        }
+    }
+    fn next(&mut self, destinations: &[Destination]) -> Option<SocketAddr> {
+        let index: u64 = self.permutation.next()?;
+        // Last host starting at or before the index. Hosts without ports share the offset of the next host.
+        let host_index: usize = self.offsets.partition_point(|offset| *offset <= index) - 1;
+        let dst: &Destination = &destinations[host_index];
+        let port_index: usize = (index - self.offsets[host_index]) as usize;
+        Some(SocketAddr::new(dst.dst_ip, dst.dst_ports[port_index]))
+    }
+}
+
+// Next host of the destinations in permuted order, or in the given order
+fn next_host(destinations: &[Destination], permutation: &mut Option<CyclicPermutation>, index: &mut usize) -> Option<IpAddr> {
+    let index: usize = match permutation {
+        Some(permutation) => permutation.next()? as usize,
+        None => {
+            *index += 1;
+            *index - 1
+        },
+    };
+    destinations.get(index).map(|dst| dst.dst_ip)
+}
+
+// Next probe to the destinations in permuted order, or in the given order
+fn next_probe(destinations: &[Destination], permutation: &mut Option<ProbePermutation>, host_index: &mut usize, port_index: &mut usize) -> Option<SocketAddr> {
+    match permutation {
+        Some(permutation) => permutation.next(destinations),
+        None => {
+            while *host_index < destinations.len() {
+                let dst: &Destination = &destinations[*host_index];
+                if *port_index < dst.dst_ports.len() {
+                    *port_index += 1;
+                    return Some(SocketAddr::new(dst.dst_ip, dst.dst_ports[*port_index - 1]));
+                }
+                *host_index += 1;
+                *port_index = 0;
+            }
+            None
+        },
+    }
+}
+
+/// Streamed destinations pulled in windows. Shuffled within each window when randomized
+struct StreamWindow {
+    target_stream: TargetStream,
+    random_seed: Option<u64>,
+    destinations: Vec<Destination>,
+    host_permutation: Option<CyclicPermutation>,
+    probe_permutation: Option<ProbePermutation>,
+    host_index: usize,
+    port_index: usize,
+    window_index: u64,
+}
+
+impl StreamWindow {
+    fn new(target_stream: TargetStream, random_seed: Option<u64>) -> StreamWindow {
+        StreamWindow {
+            target_stream: target_stream,
+            random_seed: random_seed,
+            destinations: vec![],
+            host_permutation: None,
+            probe_permutation: None,
+            host_index: 0,
+            port_index: 0,
+            window_index: 0,
+        }
+    }
+    // Pull the next window from the stream. False when the stream is exhausted
+    fn refill(&mut self, by_probe: bool) -> bool {
+        // Without randomization one destination is pulled at a time, so the stream is not buffered
+        let window_size: usize = match self.random_seed {
+            Some(_) => STREAM_WINDOW_SIZE,
+            None => 1,
+        };
+        self.destinations = self.target_stream.by_ref().take(window_size).collect();
+        // Different order in each window
+        let seed: Option<u64> = self.random_seed.map(|seed| seed ^ mix(self.window_index));
+        if by_probe {
+            self.probe_permutation = seed.map(|seed| ProbePermutation::new(&self.destinations, seed));
+        } else {
+            self.host_permutation = seed.map(|seed| CyclicPermutation::new(self.destinations.len() as u64, seed));
+        }
+        self.host_index = 0;
+        self.port_index = 0;
+        self.window_index += 1;
+        !self.destinations.is_empty()
+    }
+    fn next_host(&mut self) -> Option<IpAddr> {
+        loop {
+            if let Some(ip_addr) = next_host(&self.destinations, &mut self.host_permutation, &mut self.host_index) {
+                return Some(ip_addr);
+            }
+            if !self.refill(false) {
+                return None;
+            }
+        }
+    }
+    fn next_probe(&mut self) -> Option<SocketAddr> {
+        loop {
+            if let Some(socket_addr) = next_probe(&self.destinations, &mut self.probe_permutation, &mut self.host_index, &mut self.port_index) {
+                return Some(socket_addr);
+            }
+            if !self.refill(true) {
+                return None;
+            }
+        }
+    }
+}
+
+/// Destination IP addresses in scan order. Streamed targets follow the destinations
+pub(crate) struct HostIter<'a> {
+    destinations: &'a [Destination],
+    stream_window: Option<StreamWindow>,
+    permutation: Option<CyclicPermutation>,
+    index: usize,
+}
+
+impl<'a> HostIter<'a> {
+    pub fn new(destinations: &'a [Destination], target_stream: Option<TargetStream>, random_seed: Option<u64>) -> HostIter<'a> {
+        HostIter {
+            destinations: destinations,
+            stream_window: target_stream.map(|target_stream| StreamWindow::new(target_stream, random_seed)),
+            permutation: random_seed.map(|seed| CyclicPermutation::new(destinations.len() as u64, seed)),
+            index: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for HostIter<'a> {
+    type Item = IpAddr;
+    fn next(&mut self) -> Option<IpAddr> {
+        if let Some(ip_addr) = next_host(self.destinations, &mut self.permutation, &mut self.index) {
+            return Some(ip_addr);
+        }
+        match &mut self.stream_window {
+            Some(stream_window) => stream_window.next_host(),
+            None => None,
+        }
+    }
+}
+
+/// Destination sockets in scan order. Hosts are interleaved when randomized. Streamed targets follow the destinations
+pub(crate) struct ProbeIter<'a> {
+    destinations: &'a [Destination],
+    stream_window: Option<StreamWindow>,
+    permutation: Option<ProbePermutation>,
+    host_index: usize,
+    port_index: usize,
+}
+
+impl<'a> ProbeIter<'a> {
+    pub fn new(destinations: &'a [Destination], target_stream: Option<TargetStream>, random_seed: Option<u64>) -> ProbeIter<'a> {
+        ProbeIter {
+            destinations: destinations,
+            stream_window: target_stream.map(|target_stream| StreamWindow::new(target_stream, random_seed)),
+            permutation: random_seed.map(|seed| ProbePermutation::new(destinations, seed)),
+            host_index: 0,
+            port_index: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for ProbeIter<'a> {
+    type Item = SocketAddr;
+    fn next(&mut self) -> Option<SocketAddr> {
+        if let Some(socket_addr) = next_probe(self.destinations, &mut self.permutation, &mut self.host_index, &mut self.port_index) {
+            return Some(socket_addr);
+        }
+        match &mut self.stream_window {
+            Some(stream_window) => stream_window.next_probe(),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn destinations(n: u32) -> Vec<Destination> {
+        (0..n).map(|i| Destination::new(IpAddr::V4(Ipv4Addr::from(0x0a000000 + i)), vec![22, 80, 443])).collect()
+    }
+
+    #[test]
+    fn cyclic_permutation_visits_each_index_once() {
+        for n in [0, 1, 2, 5, 100, 1000] {
+            let mut indexes: Vec<u64> = CyclicPermutation::new(n, 7).collect();
+            indexes.sort();
+            assert_eq!(indexes, (0..n).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn streamed_probes_are_permuted() {
+        let dsts: Vec<Destination> = destinations(200);
+        let ordered: Vec<SocketAddr> = ProbeIter::new(&[], Some(TargetStream::new(dsts.clone())), None).collect();
+        let mut permuted: Vec<SocketAddr> = ProbeIter::new(&[], Some(TargetStream::new(dsts.clone())), Some(42)).collect();
+        assert_eq!(ordered.len(), 600);
+        assert_ne!(permuted, ordered);
+        // Hosts are interleaved
+        assert!(permuted.windows(2).any(|pair| pair[0].ip() != pair[1].ip()));
+        permuted.sort();
+        let mut expected: Vec<SocketAddr> = ordered.clone();
+        expected.sort();
+        assert_eq!(permuted, expected);
+    }
+
+    #[test]
+    fn uneven_port_lists_are_permuted_by_probe_count() {
+        let mut dsts: Vec<Destination> = (0..100u32).map(|i| Destination::new(IpAddr::V4(Ipv4Addr::from(0x0a000000 + i)), vec![80])).collect();
+        dsts.insert(50, Destination::new(IpAddr::V4(Ipv4Addr::new(10, 1, 0, 0)), (1..=1000).collect()));
+        dsts.insert(20, Destination::new(IpAddr::V4(Ipv4Addr::new(10, 2, 0, 0)), vec![]));
+        let mut permutation: ProbePermutation = ProbePermutation::new(&dsts, 3);
+        assert_eq!(permutation.permutation.n, 1100);
+        let mut probes: Vec<SocketAddr> = vec![];
+        while let Some(socket_addr) = permutation.next(&dsts) {
+            probes.push(socket_addr);
+        }
+        let mut expected: Vec<SocketAddr> = ProbeIter::new(&dsts, None, None).collect();
+        assert_ne!(probes, expected);
+        probes.sort();
+        expected.sort();
+        assert_eq!(probes, expected);
+    }
+
+    #[test]
+    fn streamed_hosts_are_permuted_within_windows() {
+        let dsts: Vec<Destination> = destinations(STREAM_WINDOW_SIZE as u32 + 100);
+        let ordered: Vec<IpAddr> = dsts.iter().map(|dst| dst.dst_ip).collect();
+        let permuted: Vec<IpAddr> = HostIter::new(&[], Some(TargetStream::new(dsts.clone())), Some(42)).collect();
+        assert_ne!(permuted, ordered);
+        // Every host of a window is sent before the next window is pulled
+        let mut first_window: Vec<IpAddr> = permuted[..STREAM_WINDOW_SIZE].to_vec();
+        first_window.sort();
+        assert_eq!(first_window, ordered[..STREAM_WINDOW_SIZE].to_vec());
+        let mut rest: Vec<IpAddr> = permuted[STREAM_WINDOW_SIZE..].to_vec();
+        rest.sort();
+        assert_eq!(rest, ordered[STREAM_WINDOW_SIZE..].to_vec());
+    }
+
+    #[test]
+    fn streamed_targets_keep_order_without_seed() {
+        let dsts: Vec<Destination> = destinations(10);
+        let fixed: Vec<Destination> = destinations(3);
+        let hosts: Vec<IpAddr> = HostIter::new(&fixed, Some(TargetStream::new(dsts.clone())), None).collect();
+        let expected: Vec<IpAddr> = fixed.iter().chain(dsts.iter()).map(|dst| dst.dst_ip).collect();
+        assert_eq!(hosts, expected);
+    }
+}
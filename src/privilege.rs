@@ -0,0 +1,65 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use socket2::{Domain, Protocol, Socket, Type};
+use crate::setting::{ScanType, FallbackPolicy};
+use crate::result::ScanTechnique;
+use crate::error::Error;
+
+/// Check if raw sockets can be opened for the address family of the source IP address
+///
+/// Requires root or CAP_NET_RAW on Linux, administrator on Windows.
+pub fn has_raw_socket_privilege(src_ip: IpAddr) -> bool {
+    Socket::new(Domain::for_address(SocketAddr::new(src_ip, 0)), Type::RAW, Some(Protocol::TCP)).is_ok()
+}
+
+/// Check if unprivileged ICMP echo sockets (SOCK_DGRAM + IPPROTO_ICMP) can be opened
+///
+/// Allowed on Linux for the groups in net.ipv4.ping_group_range.
+#[cfg(not(target_os="windows"))]
+pub(crate) fn has_datagram_icmp_socket(src_ip: IpAddr) -> bool {
+    let socket = match src_ip {
+        IpAddr::V4(_) => Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)),
+        IpAddr::V6(_) => Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::ICMPV6)),
+    };
+    socket.is_ok()
+}
+
+#[cfg(target_os="windows")]
+pub(crate) fn has_datagram_icmp_socket(_src_ip: IpAddr) -> bool {
+    false
+}
+
+/// Technique to run the scan type under the fallback policy
+///
+/// Fails with permission error if privileges are missing and the fallback is disabled.
+pub(crate) fn get_scan_technique(scan_type: &ScanType, src_ip: IpAddr, fallback_policy: FallbackPolicy) -> Result<ScanTechnique, Error> {
+    let requested = ScanTechnique {
+        scan_type: scan_type.clone(),
+        privileged: scan_type.requires_privilege(),
+        fallback: false,
+    };
+    if !scan_type.requires_privilege() || has_raw_socket_privilege(src_ip) {
+        return Ok(requested);
+    }
+    if fallback_policy == FallbackPolicy::Disabled {
+        return Err(Error::PermissionDenied(io::Error::new(io::ErrorKind::PermissionDenied, "Fallback is disabled")));
+    }
+    // Datagram ICMP socket in place of the raw socket. Same scan type, but a substitute technique.
+    if matches!(scan_type, ScanType::IcmpPingScan) && has_datagram_icmp_socket(src_ip) {
+        return Ok(ScanTechnique {
+            scan_type: ScanType::IcmpPingScan,
+            privileged: false,
+            fallback: true,
+        });
+    }
+    match scan_type.get_unprivileged_fallback() {
+        Some(fallback_type) => {
+            Ok(ScanTechnique {
+                scan_type: fallback_type,
+                privileged: false,
+                fallback: true,
+            })
+        },
+        None => Ok(requested),
+    }
+}
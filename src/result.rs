@@ -1,6 +1,11 @@
+use std::io;
 use std::net::{IpAddr, SocketAddr};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::collections::{HashSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use crate::setting::{Destination, ScanType};
+use crate::timing::RttEstimator;
 
 /// Status of scan task 
 #[derive(Clone, Debug, PartialEq)]
@@ -9,6 +14,38 @@ pub enum ScanStatus {
     Done,
     Timeout,
     Error,
+    /// Stopped by CancelToken. Holds the replies received until then
+    Cancelled,
+}
+
+/// Progress event of the running scan, delivered over the progress channel
+#[derive(Clone, Debug)]
+pub enum ScanEvent {
+    /// Scan started. Number of probes to send, None for streamed targets
+    Started { total: Option<usize> },
+    /// Probe sent to the socket. Port 0 for host probes
+    ProbeSent(SocketAddr),
+    /// Host responded
+    HostUp(HostInfo),
+    /// State of the port determined
+    PortState(IpAddr, PortInfo),
+    /// Probe without response sent again
+    Retry(SocketAddr),
+    /// Scan finished with the status
+    Finished { status: ScanStatus },
+}
+
+/// Send the event to the progress channel. Ignored if the receiver has been dropped.
+pub(crate) fn send_event(event_tx: &Arc<Mutex<Sender<ScanEvent>>>, event: ScanEvent) {
+    match event_tx.lock() {
+        Ok(lr) => {
+            match lr.send(event) {
+                Ok(_) => {},
+                Err(_) => {},
+            }
+        },
+        Err(_) => {},
+    }
 }
 
 /// Status of the scanned port 
@@ -16,7 +53,54 @@ pub enum ScanStatus {
 pub enum PortStatus {
     Open,
     Closed,
+    /// No response or ICMP unreachable (SYN, ACK, Window)
     Filtered,
+    /// No response. Port is open or filtered (UDP, FIN, NULL, Xmas, Maimon)
+    OpenFiltered,
+    /// Reachable, but open or closed cannot be determined (ACK)
+    Unfiltered,
+}
+
+/// Error of the scanned host 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostError {
+    /// No route to the host 
+    HostUnreachable,
+    /// No route to the network 
+    NetworkUnreachable,
+    /// Socket for the probe could not be opened (e.g. too many open files) 
+    SocketOpen,
+}
+
+// OS error codes of unreachable host and network
+#[cfg(not(target_os="windows"))]
+const HOST_UNREACHABLE: i32 = libc::EHOSTUNREACH;
+#[cfg(not(target_os="windows"))]
+const NETWORK_UNREACHABLE: i32 = libc::ENETUNREACH;
+#[cfg(target_os="windows")]
+const HOST_UNREACHABLE: i32 = winapi::shared::winerror::WSAEHOSTUNREACH as i32;
+#[cfg(target_os="windows")]
+const NETWORK_UNREACHABLE: i32 = winapi::shared::winerror::WSAENETUNREACH as i32;
+
+impl HostError {
+    pub(crate) fn from_io_error(error: &io::Error) -> Option<HostError> {
+        match error.raw_os_error() {
+            Some(HOST_UNREACHABLE) => Some(HostError::HostUnreachable),
+            Some(NETWORK_UNREACHABLE) => Some(HostError::NetworkUnreachable),
+            _ => None,
+        }
+    }
+}
+
+/// Technique actually used for the scan 
+#[derive(Clone, Debug)]
+pub struct ScanTechnique {
+    /// Scan type that was run 
+    pub scan_type: ScanType,
+    /// Probes sent through raw socket or datalink channel 
+    pub privileged: bool,
+    /// Downgraded from the requested scan type or technique by the fallback policy 
+    pub fallback: bool,
 }
 
 /// Information about the scanned host 
@@ -28,6 +112,10 @@ pub struct HostInfo {
     pub ttl: u8,
     /// Ports used for host scan
     pub ports: Vec<PortInfo>,
+    /// MAC address of the host (ARP/NDP scan)
+    pub mac_addr: Option<[u8; 6]>,
+    /// Round-trip time of the first matched reply
+    pub rtt: Option<Duration>,
 }
 
 /// Information about the scanned port 
@@ -37,6 +125,10 @@ pub struct PortInfo {
     pub port: u16,
     /// Port status
     pub status: PortStatus,
+    /// TCP window size of the response
+    pub tcp_window: Option<u16>,
+    /// Round-trip time of the probe
+    pub rtt: Option<Duration>,
 }
 
 /// Result of host scan 
@@ -48,6 +140,8 @@ pub struct HostScanResult {
     pub scan_time: Duration,
     /// Status of the scan task
     pub scan_status: ScanStatus,
+    /// Technique actually used. None until the scan is run
+    pub technique: Option<ScanTechnique>,
 }
 
 impl HostScanResult {
@@ -56,6 +150,7 @@ impl HostScanResult {
             hosts: vec![],
             scan_time: Duration::from_millis(0),
             scan_status: ScanStatus::Ready,
+            technique: None,
         }
     }
     /// Returns IP addresses from the scan result
@@ -73,18 +168,24 @@ impl HostScanResult {
 pub struct PortScanResult {
     /// HashMap of scanned IP addresses and their respective port scan results.
     pub result_map: HashMap<IpAddr, Vec<PortInfo>>,
+    /// Hosts that could not be reached (TCP connect scan)
+    pub host_errors: HashMap<IpAddr, HostError>,
     /// Time taken to scan
     pub scan_time: Duration,
     /// Status of the scan task
     pub scan_status: ScanStatus,
+    /// Technique actually used. None until the scan is run
+    pub technique: Option<ScanTechnique>,
 }
 
 impl PortScanResult {
     pub fn new() -> PortScanResult {
         PortScanResult{
             result_map: HashMap::new(),
+            host_errors: HashMap::new(),
             scan_time: Duration::from_millis(0),
             scan_status: ScanStatus::Ready,
+            technique: None,
         }
     }
     /// Get open ports of the specified IP address from the scan results
@@ -104,12 +205,26 @@ impl PortScanResult {
     }
 }
 
+/// Outstanding ICMP echo request
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct EchoRequest {
+    pub identifier: u16,
+    pub sequence_number: u16,
+    pub send_time: Instant,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct ScanResult {
     pub host_scan_result: HostScanResult,
     pub port_scan_result: PortScanResult,
     pub ip_set: HashSet<IpAddr>,
     pub socket_set: HashSet<SocketAddr>,
+    pub echo_requests: HashMap<IpAddr, Vec<EchoRequest>>,
+    pub probe_times: HashMap<SocketAddr, Instant>,
+    pub retransmitted_probes: HashSet<SocketAddr>,
+    pub rtt_estimators: HashMap<IpAddr, RttEstimator>,
+    pub global_rtt_estimator: RttEstimator,
+    pub event_tx: Option<Arc<Mutex<Sender<ScanEvent>>>>,
 }
 
 impl ScanResult {
@@ -119,6 +234,141 @@ impl ScanResult {
             port_scan_result: PortScanResult::new(),
             ip_set: HashSet::new(),
             socket_set: HashSet::new(),
+            echo_requests: HashMap::new(),
+            probe_times: HashMap::new(),
+            retransmitted_probes: HashSet::new(),
+            rtt_estimators: HashMap::new(),
+            global_rtt_estimator: RttEstimator::new(),
+            event_tx: None,
+        }
+    }
+    /// Create new ScanResult reporting the hosts and ports found to the progress channel
+    pub fn new_with_event_sender(event_tx: &Arc<Mutex<Sender<ScanEvent>>>) -> ScanResult {
+        let mut scan_result: ScanResult = ScanResult::new();
+        scan_result.event_tx = Some(Arc::clone(event_tx));
+        scan_result
+    }
+    fn send_event(&self, event: ScanEvent) {
+        match &self.event_tx {
+            Some(event_tx) => send_event(event_tx, event),
+            None => {},
+        }
+    }
+    /// Record the send time of the probe to the socket
+    pub fn add_probe(&mut self, socket_addr: SocketAddr) {
+        if self.probe_times.contains_key(&socket_addr) {
+            self.retransmitted_probes.insert(socket_addr);
+        }
+        self.probe_times.insert(socket_addr, Instant::now());
+    }
+    /// Match the reply against the outstanding probe to the socket and return the round-trip time
+    pub fn match_probe(&mut self, socket_addr: SocketAddr) -> Option<Duration> {
+        match self.probe_times.remove(&socket_addr) {
+            Some(send_time) => {
+                let rtt: Duration = Instant::now().duration_since(send_time);
+                // Reply to a retransmitted probe is ambiguous. Skip it for the estimate (Karn's algorithm).
+                if !self.retransmitted_probes.contains(&socket_addr) {
+                    self.add_rtt_sample(socket_addr.ip(), rtt);
+                }
+                Some(rtt)
+            },
+            None => None,
+        }
+    }
+    /// Update the RTT estimate of the host and of the whole scan
+    pub fn add_rtt_sample(&mut self, ip_addr: IpAddr, rtt: Duration) {
+        self.rtt_estimators.entry(ip_addr).or_insert(RttEstimator::new()).update(rtt);
+        self.global_rtt_estimator.update(rtt);
+    }
+    /// Probe timeout for the host. Falls back to the estimate of the whole scan for hosts without sample.
+    pub fn get_probe_timeout(&self, ip_addr: IpAddr) -> Duration {
+        match self.rtt_estimators.get(&ip_addr) {
+            Some(rtt_estimator) => rtt_estimator.get_timeout(),
+            None => self.global_rtt_estimator.get_timeout(),
+        }
+    }
+    /// Wait time covering the slowest measured host. None if no RTT sample has been taken.
+    pub fn get_adaptive_wait_time(&self) -> Option<Duration> {
+        if !self.global_rtt_estimator.has_sample() {
+            return None;
+        }
+        let mut wait_time: Duration = self.global_rtt_estimator.get_timeout();
+        for rtt_estimator in self.rtt_estimators.values() {
+            if rtt_estimator.get_timeout() > wait_time {
+                wait_time = rtt_estimator.get_timeout();
+            }
+        }
+        Some(wait_time)
+    }
+    /// Record the echo request sent to the destination. Earlier requests remain valid for retransmission.
+    pub fn add_echo_request(&mut self, ip_addr: IpAddr, identifier: u16, sequence_number: u16) {
+        let echo_request = EchoRequest {
+            identifier: identifier,
+            sequence_number: sequence_number,
+            send_time: Instant::now(),
+        };
+        self.echo_requests.entry(ip_addr).or_insert(vec![]).push(echo_request);
+    }
+    /// Match the echo reply against the outstanding requests and return the round-trip time
+    pub fn match_echo_reply(&mut self, ip_addr: IpAddr, identifier: u16, sequence_number: u16) -> Option<Duration> {
+        let send_time: Instant = match self.echo_requests.get(&ip_addr) {
+            Some(echo_requests) => {
+                match echo_requests.iter().find(|echo_request| echo_request.identifier == identifier && echo_request.sequence_number == sequence_number) {
+                    Some(echo_request) => echo_request.send_time,
+                    None => return None,
+                }
+            },
+            None => return None,
+        };
+        self.echo_requests.remove(&ip_addr);
+        let rtt: Duration = Instant::now().duration_since(send_time);
+        self.add_rtt_sample(ip_addr, rtt);
+        Some(rtt)
+    }
+    /// Add port info for the socket if it has not been recorded yet
+    pub fn add_port_info(&mut self, ip_addr: IpAddr, port_info: PortInfo) {
+        let socket_addr: SocketAddr = SocketAddr::new(ip_addr, port_info.port);
+        if self.socket_set.contains(&socket_addr) {
+            return;
+        }
+        self.port_scan_result.result_map.entry(ip_addr).or_insert(vec![]).push(port_info);
+        self.socket_set.insert(socket_addr);
+        self.send_event(ScanEvent::PortState(ip_addr, port_info));
+    }
+    /// Add host info if the host has not been recorded yet
+    pub fn add_host_info(&mut self, host_info: HostInfo) {
+        if self.ip_set.contains(&host_info.ip_addr) {
+            return;
+        }
+        self.ip_set.insert(host_info.ip_addr);
+        self.host_scan_result.hosts.push(host_info.clone());
+        self.send_event(ScanEvent::HostUp(host_info));
+    }
+    /// Add port info with the specified status for every sent probe without response
+    pub fn add_unanswered_probes(&mut self, status: PortStatus) {
+        let socket_addrs: Vec<SocketAddr> = self.probe_times.keys().cloned().collect();
+        for socket_addr in socket_addrs {
+            let port_info = PortInfo {
+                port: socket_addr.port(),
+                status: status,
+                tcp_window: None,
+                rtt: None,
+            };
+            self.add_port_info(socket_addr.ip(), port_info);
+        }
+    }
+    /// Add port info with the specified status for every probed port without response
+    pub fn add_unanswered_ports(&mut self, destinations: &Vec<Destination>, status: PortStatus) {
+        for dst in destinations {
+            for port in &dst.dst_ports {
+                let port_info = PortInfo {
+                    port: *port,
+                    status: status,
+                    tcp_window: None,
+                    rtt: None,
+                };
+                self.add_port_info(dst.dst_ip, port_info);
+            }
         }
     }
 }
@@ -1,11 +1,22 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
-use std::collections::HashSet;
+use std::collections::{HashSet, HashMap};
+use std::sync::{Arc, Mutex};
 use pnet_datalink::MacAddr;
+use crate::packet::payload;
+use crate::result::{PortStatus, ScanResult, ScanEvent};
+use crate::timing::{RateController, TokenBucket};
+use crate::ports;
+use crate::permutation::{HostIter, ProbeIter};
+use crate::target::TargetStream;
+use crate::cookie::{CookieKey, COOKIE_PORT_RANGE};
+use crate::cancel::CancelToken;
 
 pub(crate) const DEFAULT_SRC_PORT: u16 = 53443;
 pub(crate) const DEFAULT_HOSTS_CONCURRENCY: usize = 50;
 pub(crate) const DEFAULT_PORTS_CONCURRENCY: usize = 100;
+pub(crate) const DEFAULT_CONN_TIMEOUT: Duration = Duration::from_millis(200);
+pub(crate) const DEFAULT_CONNECT_PING_PORTS: [u16; 2] = [80, 443];
 
 /// Scan Type 
 #[derive(Clone, Debug)]
@@ -26,7 +37,84 @@ pub enum ScanType {
     /// 
     /// Send TCP packets with SYN flag to a specific port and check response.
     TcpPingScan,
+    /// Attempt TCP connection to the destination ports. Up on connection or refusal.
+    /// 
+    /// Can be run without administrator privileges. Port 80 and 443 if no ports are specified.
+    TcpConnectPingScan,
     UdpPingScan,
+    /// Send UDP datagram to the target ports and check response.
+    /// 
+    /// Open on UDP reply, Closed on ICMP port unreachable, 
+    /// Filtered on other ICMP unreachable and OpenFiltered on no response.
+    UdpScan,
+    /// Send TCP packet with FIN flag. 
+    /// 
+    /// Closed on RST, OpenFiltered on no response. (RFC 793 compliant stacks)
+    TcpFinScan,
+    /// Send TCP packet with no flags set. 
+    /// 
+    /// Closed on RST, OpenFiltered on no response. (RFC 793 compliant stacks)
+    TcpNullScan,
+    /// Send TCP packet with FIN, PSH and URG flags. 
+    /// 
+    /// Closed on RST, OpenFiltered on no response. (RFC 793 compliant stacks)
+    TcpXmasScan,
+    /// Send TCP packet with ACK flag. 
+    /// 
+    /// Unfiltered on RST, Filtered on no response. Used to map firewall rulesets.
+    TcpAckScan,
+    /// Send TCP packet with FIN and ACK flags. 
+    /// 
+    /// Closed on RST, OpenFiltered on no response. (Some BSD-derived stacks)
+    TcpMaimonScan,
+    /// Send TCP packet with ACK flag and check the window size of RST response. 
+    /// 
+    /// Open on non-zero window, Closed on zero window, Filtered on no response. 
+    /// Only reliable on stacks that report non-zero window for open ports.
+    TcpWindowScan,
+    /// Send ARP request and check reply. (IPv4 only)
+    /// 
+    /// Discover hosts on the local network segment, with their MAC address.
+    ArpScan,
+    /// Send ICMPv6 Neighbor Solicitation to solicited-node multicast address and check Neighbor Advertisement. (IPv6 only)
+    /// 
+    /// Discover hosts on the local network segment, with their MAC address.
+    NdpScan,
+}
+
+impl ScanType {
+    /// Status of the probed ports without response
+    pub(crate) fn get_unanswered_port_status(&self) -> Option<PortStatus> {
+        match self {
+            ScanType::UdpScan | ScanType::TcpFinScan | ScanType::TcpNullScan | ScanType::TcpXmasScan | ScanType::TcpMaimonScan => Some(PortStatus::OpenFiltered),
+            ScanType::TcpSynScan | ScanType::TcpAckScan | ScanType::TcpWindowScan => Some(PortStatus::Filtered),
+            _ => None,
+        }
+    }
+    /// Requires raw socket or datalink channel (root or CAP_NET_RAW, administrator on Windows)
+    pub(crate) fn requires_privilege(&self) -> bool {
+        !matches!(self, ScanType::TcpConnectScan | ScanType::TcpConnectPingScan)
+    }
+    /// Unprivileged scan type for the same task. None if there is no alternative
+    pub(crate) fn get_unprivileged_fallback(&self) -> Option<ScanType> {
+        match self {
+            ScanType::TcpSynScan => Some(ScanType::TcpConnectScan),
+            ScanType::IcmpPingScan | ScanType::TcpPingScan => Some(ScanType::TcpConnectPingScan),
+            _ => None,
+        }
+    }
+}
+
+/// Policy when the scan type requires privileges that are not available 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FallbackPolicy {
+    /// Run the scan type as set. Fails with permission error without privileges 
+    Disabled,
+    /// Downgrade to an unprivileged scan type. 
+    /// 
+    /// TCP SYN scan to TCP connect scan, ICMP and TCP ping to TCP connect ping. 
+    /// ICMP ping uses datagram ICMP socket instead if allowed.
+    Unprivileged,
 }
 
 /// Struct of destination information 
@@ -59,6 +147,20 @@ impl Destination {
             dst_ports: ports,
         }
     }
+    /// Create new Destination with IP address and the top N TCP ports 
+    pub fn new_with_top_ports(ip_addr: IpAddr, n: usize) -> Destination {
+        Destination {
+            dst_ip: ip_addr,
+            dst_ports: ports::get_top_tcp_ports(n),
+        }
+    }
+    /// Create new Destination with IP address and the top N UDP ports 
+    pub fn new_with_top_udp_ports(ip_addr: IpAddr, n: usize) -> Destination {
+        Destination {
+            dst_ip: ip_addr,
+            dst_ports: ports::get_top_udp_ports(n),
+        }
+    }
     /// Set destination IP address
     pub fn set_dst_ip(&mut self, ip_addr: IpAddr) {
         self.dst_ip = ip_addr;
@@ -77,10 +179,116 @@ impl Destination {
     }
 }
 
+/// UDP payloads to send, by destination port. 
+/// 
+/// Most UDP services do not respond to an empty datagram. 
+#[derive(Clone, Debug)]
+pub struct UdpPayloadDatabase {
+    pub payloads: HashMap<u16, Vec<u8>>,
+}
+
+impl UdpPayloadDatabase {
+    /// Create new empty UdpPayloadDatabase
+    pub fn new() -> UdpPayloadDatabase {
+        UdpPayloadDatabase {
+            payloads: HashMap::new(),
+        }
+    }
+    /// Add payload for the port. Replaces existing payload.
+    pub fn add_payload(&mut self, port: u16, payload: Vec<u8>) {
+        self.payloads.insert(port, payload);
+    }
+    /// Remove payload for the port
+    pub fn remove_payload(&mut self, port: u16) {
+        self.payloads.remove(&port);
+    }
+    /// Send SNMP v2c GetRequest instead of v1 to port 161
+    pub fn use_snmp_v2c(&mut self) {
+        self.payloads.insert(161, payload::SNMP_V2C_GET_REQUEST.to_vec());
+    }
+    /// Get payload for the port. Empty if not registered.
+    pub fn get_payload(&self, port: u16) -> Vec<u8> {
+        match self.payloads.get(&port) {
+            Some(payload) => payload.clone(),
+            None => vec![],
+        }
+    }
+}
+
+impl Default for UdpPayloadDatabase {
+    /// Create new UdpPayloadDatabase with built-in payloads 
+    /// 
+    /// DNS, NTP, NetBIOS, SNMP, MS-SQL, SSDP and mDNS
+    fn default() -> Self {
+        let mut payloads: HashMap<u16, Vec<u8>> = HashMap::new();
+        payloads.insert(53, payload::DNS_VERSION_BIND.to_vec());
+        payloads.insert(123, payload::NTP_REQUEST.to_vec());
+        payloads.insert(137, payload::NETBIOS_NAME_QUERY.to_vec());
+        payloads.insert(161, payload::SNMP_GET_REQUEST.to_vec());
+        payloads.insert(1434, payload::MSSQL_PING.to_vec());
+        payloads.insert(1900, payload::SSDP_M_SEARCH.to_vec());
+        payloads.insert(5353, payload::MDNS_SERVICES_QUERY.to_vec());
+        UdpPayloadDatabase {
+            payloads: payloads,
+        }
+    }
+}
+
+/// Congestion-aware send rate 
+/// 
+/// Starts at the ceiling, backs off when the response ratio drops (drops or rate limiting) 
+/// and ramps up while responses keep flowing.
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicSendRate {
+    /// Floor in packets per second 
+    pub min_pps: u32,
+    /// Ceiling in packets per second 
+    pub max_pps: u32,
+}
+
+impl DynamicSendRate {
+    /// Create new DynamicSendRate with floor and ceiling in packets per second
+    pub fn new(min_pps: u32, max_pps: u32) -> DynamicSendRate {
+        DynamicSendRate {
+            min_pps: min_pps,
+            max_pps: max_pps,
+        }
+    }
+}
+
+impl Default for DynamicSendRate {
+    fn default() -> Self {
+        DynamicSendRate::new(50, 5000)
+    }
+}
+
+/// Hard cap on sending rate across all destinations and concurrency levels 
+#[derive(Clone, Copy, Debug)]
+pub enum RateLimit {
+    /// Packets per second 
+    PacketsPerSecond(u32),
+    /// Bits per second on the wire, including Ethernet and IP headers 
+    BitsPerSecond(u64),
+}
+
+impl RateLimit {
+    pub(crate) fn into_token_bucket(self) -> TokenBucket {
+        match self {
+            RateLimit::PacketsPerSecond(pps) => {
+                // No burst beyond a single packet
+                TokenBucket::new(pps.max(1) as f64, 1.0, false)
+            },
+            RateLimit::BitsPerSecond(bps) => {
+                // No burst beyond a single full-size Ethernet frame
+                TokenBucket::new(bps.max(1) as f64, (1514 * 8) as f64, true)
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct ScanSetting {
     pub if_index: u32,
-    #[allow(dead_code)]
     pub src_mac: MacAddr,
     #[allow(dead_code)]
     pub dst_mac: MacAddr,
@@ -88,12 +296,212 @@ pub(crate) struct ScanSetting {
     pub src_port: u16,
     pub destinations: Vec<Destination>,
     pub ip_set: HashSet<IpAddr>,
+    pub target_stream: Option<TargetStream>,
+    pub cookie_key: CookieKey,
     pub timeout: Duration,
     pub wait_time: Duration,
     pub send_rate: Duration,
+    pub rate_controller: Option<Arc<Mutex<RateController>>>,
+    pub rate_limiter: Option<Arc<Mutex<TokenBucket>>>,
+    pub adaptive_timing: bool,
+    pub retry_count: u8,
+    pub retransmission: bool,
+    pub random_seed: Option<u64>,
     pub scan_type: ScanType,
     #[allow(dead_code)]
+    pub privileged: bool,
+    pub echo_identifier: Option<u16>,
+    pub udp_payloads: UdpPayloadDatabase,
+    pub cancel_token: CancelToken,
+    #[allow(dead_code)]
     pub hosts_concurrency: usize,
     #[allow(dead_code)]
     pub ports_concurrency: usize,
 }
+
+impl ScanSetting {
+    /// Delay before sending the packet. Dynamic send rate or fixed send rate, capped by the rate limit.
+    pub fn get_send_delay(&self, packet_len: usize) -> Duration {
+        let send_delay: Duration = match &self.rate_controller {
+            Some(rate_controller) => rate_controller.lock().unwrap().next_send_delay(),
+            None => self.send_rate,
+        };
+        let rate_limit_delay: Duration = self.get_rate_limit_delay(packet_len);
+        if rate_limit_delay > send_delay { rate_limit_delay } else { send_delay }
+    }
+    /// Delay before sending the packet by the rate limit. Shared by all senders of the scan.
+    pub fn get_rate_limit_delay(&self, packet_len: usize) -> Duration {
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.lock().unwrap().reserve(packet_len),
+            None => Duration::from_millis(0),
+        }
+    }
+    /// Count the response to a probe for dynamic send rate
+    pub fn add_response(&self) {
+        match &self.rate_controller {
+            Some(rate_controller) => rate_controller.lock().unwrap().add_response(),
+            None => {},
+        }
+    }
+    /// Waiting time after sending. Derived from the measured RTT in adaptive timing mode.
+    pub fn get_wait_time(&self, scan_result: &Arc<Mutex<ScanResult>>) -> Duration {
+        if !self.adaptive_timing {
+            return self.wait_time;
+        }
+        match scan_result.lock().unwrap().get_adaptive_wait_time() {
+            Some(wait_time) => wait_time,
+            None => self.wait_time,
+        }
+    }
+    /// Sleep for the waiting time. Returns early if the scan is cancelled.
+    pub fn wait(&self, scan_result: &Arc<Mutex<ScanResult>>) {
+        self.cancel_token.sleep(self.get_wait_time(scan_result));
+    }
+    /// Check if the scan has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_token.is_cancelled()
+    }
+    /// Connection timeout for the host. Derived from the measured RTT in adaptive timing mode.
+    pub fn get_conn_timeout(&self, ip_addr: IpAddr, scan_result: &Arc<Mutex<ScanResult>>) -> Duration {
+        if !self.adaptive_timing {
+            return DEFAULT_CONN_TIMEOUT;
+        }
+        scan_result.lock().unwrap().get_probe_timeout(ip_addr)
+    }
+    /// Destination IP addresses in scan order. Shuffled if random seed is set. Ends when the scan is cancelled.
+    pub fn get_host_iter(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        HostIter::new(&self.destinations, self.target_stream.clone(), self.random_seed).take_while(move |_| !self.is_cancelled())
+    }
+    /// Destination sockets in scan order. Hosts are interleaved and ports shuffled if random seed is set. Ends when the scan is cancelled.
+    pub fn get_probe_iter(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        ProbeIter::new(&self.destinations, self.target_stream.clone(), self.random_seed).take_while(move |_| !self.is_cancelled())
+    }
+    /// Destinations in the specified order (TCP connect scan). Ends when the scan is cancelled.
+    pub fn get_destination_iter(&self) -> Box<dyn Iterator<Item = Destination> + Send> {
+        let cancel_token: CancelToken = self.cancel_token.clone();
+        match &self.target_stream {
+            Some(target_stream) => Box::new(target_stream.clone().take_while(move |_| !cancel_token.is_cancelled())),
+            None => Box::new(self.destinations.clone().into_iter().take_while(move |_| !cancel_token.is_cancelled())),
+        }
+    }
+    /// Ports for TCP connect ping. Port 80 and 443 if the destination has no ports.
+    pub fn get_ping_ports(&self, dst: &Destination) -> Vec<u16> {
+        if dst.dst_ports.is_empty() {
+            return DEFAULT_CONNECT_PING_PORTS.to_vec();
+        }
+        dst.dst_ports.clone()
+    }
+    /// Number of probes to send, without retransmissions. None for streamed targets.
+    pub fn get_probe_count(&self) -> Option<usize> {
+        if self.is_stateless() {
+            return None;
+        }
+        match self.scan_type {
+            ScanType::IcmpPingScan | ScanType::ArpScan | ScanType::NdpScan | ScanType::TcpConnectPingScan => Some(self.destinations.len()),
+            _ => Some(self.destinations.iter().map(|dst| dst.dst_ports.len()).sum()),
+        }
+    }
+    /// Progress event for the probe to the socket. Retry if the probe is retransmitted.
+    pub fn get_probe_event(&self, socket_addr: SocketAddr) -> ScanEvent {
+        if self.retransmission {
+            ScanEvent::Retry(socket_addr)
+        } else {
+            ScanEvent::ProbeSent(socket_addr)
+        }
+    }
+    /// Targets are streamed and replies are matched by cookie instead of per-probe state
+    pub fn is_stateless(&self) -> bool {
+        self.target_stream.is_some()
+    }
+    /// Source port of the probe to the destination. Carries the cookie in stateless mode.
+    pub fn get_src_port(&self, dst_ip: IpAddr, dst_port: u16) -> u16 {
+        if !self.is_stateless() {
+            return self.src_port;
+        }
+        let base_port: u16 = self.src_port.min(u16::MAX - (COOKIE_PORT_RANGE - 1));
+        let cookie: u32 = self.cookie_key.get_port_cookie(dst_ip, dst_port);
+        base_port + (cookie % COOKIE_PORT_RANGE as u32) as u16
+    }
+    /// Initial sequence number of the TCP probe. Keyed hash of the destination and source port.
+    pub fn get_tcp_sequence(&self, dst_ip: IpAddr, dst_port: u16, src_port: u16) -> u32 {
+        self.cookie_key.get_cookie(dst_ip, dst_port, src_port)
+    }
+    /// Check if the reply comes from one of the targets. Any host is accepted in stateless mode.
+    pub fn is_target_host(&self, ip_addr: &IpAddr) -> bool {
+        if self.is_stateless() {
+            return true;
+        }
+        self.ip_set.contains(ip_addr)
+    }
+    /// Check if the reply from the remote port was sent to the source port of the probe
+    pub fn is_probe_port(&self, remote_ip: IpAddr, remote_port: u16, local_port: u16) -> bool {
+        local_port == self.get_src_port(remote_ip, remote_port)
+    }
+    /// Identifier and sequence number of the echo request. Derived from the cookie in stateless mode.
+    /// The identifier of the datagram ICMP socket is used if set.
+    pub fn get_echo_id(&self, ip_addr: IpAddr) -> (u16, u16) {
+        let (identifier, sequence_number) = if self.is_stateless() {
+            let cookie: u32 = self.cookie_key.get_host_cookie(ip_addr);
+            ((cookie >> 16) as u16, cookie as u16)
+        } else {
+            (rand::random::<u16>(), rand::random::<u16>())
+        };
+        match self.echo_identifier {
+            Some(echo_identifier) => (echo_identifier, sequence_number),
+            None => (identifier, sequence_number),
+        }
+    }
+    /// Record the probe for RTT and retransmission. Nothing is recorded in stateless mode.
+    pub fn add_probe(&self, scan_result: &Arc<Mutex<ScanResult>>, socket_addr: SocketAddr) {
+        if !self.is_stateless() {
+            scan_result.lock().unwrap().add_probe(socket_addr);
+        }
+    }
+    /// Record the echo request for matching the reply. Nothing is recorded in stateless mode.
+    pub fn add_echo_request(&self, scan_result: &Arc<Mutex<ScanResult>>, ip_addr: IpAddr, identifier: u16, sequence_number: u16) {
+        if !self.is_stateless() {
+            scan_result.lock().unwrap().add_echo_request(ip_addr, identifier, sequence_number);
+        }
+    }
+    /// Setting to resend the probes without answer. None if every probe has been answered or the scan is cancelled.
+    /// Streamed targets are not retried.
+    pub fn get_retry_setting(&self, scan_result: &Arc<Mutex<ScanResult>>) -> Option<ScanSetting> {
+        if self.is_stateless() || self.is_cancelled() {
+            return None;
+        }
+        let mut destinations: Vec<Destination> = vec![];
+        let result = scan_result.lock().unwrap();
+        match self.scan_type {
+            ScanType::IcmpPingScan | ScanType::TcpPingScan | ScanType::UdpPingScan | ScanType::ArpScan | ScanType::NdpScan => {
+                for dst in &self.destinations {
+                    if !result.ip_set.contains(&dst.dst_ip) {
+                        destinations.push(dst.clone());
+                    }
+                }
+            },
+            _ => {
+                for dst in &self.destinations {
+                    let mut dst_ports: Vec<u16> = vec![];
+                    for port in &dst.dst_ports {
+                        if !result.socket_set.contains(&SocketAddr::new(dst.dst_ip, *port)) {
+                            dst_ports.push(*port);
+                        }
+                    }
+                    if !dst_ports.is_empty() {
+                        destinations.push(Destination {
+                            dst_ip: dst.dst_ip,
+                            dst_ports: dst_ports,
+                        });
+                    }
+                }
+            },
+        }
+        if destinations.is_empty() {
+            return None;
+        }
+        let mut retry_setting: ScanSetting = self.clone();
+        retry_setting.destinations = destinations;
+        retry_setting.retransmission = true;
+        Some(retry_setting)
+    }
+}
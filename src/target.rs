@@ -0,0 +1,476 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use crate::setting::{Destination, ScanType};
+
+/// Maximum number of hosts a single target can expand to
+pub const MAX_TARGET_HOSTS: u128 = 1 << 24;
+
+/// Ports separated by protocol
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortSpec {
+    /// TCP ports
+    pub tcp_ports: Vec<u16>,
+    /// UDP ports
+    pub udp_ports: Vec<u16>,
+}
+
+impl PortSpec {
+    /// Get ports for the scan type. UDP ports for UDP scans, TCP ports otherwise
+    pub fn get_ports(&self, scan_type: &ScanType) -> Vec<u16> {
+        match scan_type {
+            ScanType::UdpScan | ScanType::UdpPingScan => self.udp_ports.clone(),
+            _ => self.tcp_ports.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PortProtocol {
+    All,
+    Tcp,
+    Udp,
+}
+
+// Addresses of a single target, without expanding them
+#[derive(Clone, Debug)]
+enum TargetRange {
+    V4(u32, u32),
+    V6(u128, u128),
+    Octets([(u8, u8); 4]),
+}
+
+impl TargetRange {
+    fn len(&self) -> u128 {
+        match self {
+            TargetRange::V4(start, end) => (*end - *start) as u128 + 1,
+            TargetRange::V6(start, end) => (*end - *start).saturating_add(1),
+            TargetRange::Octets(octets) => octets.iter().map(|(start, end)| (*end - *start) as u128 + 1).product(),
+        }
+    }
+    fn contains(&self, ip_addr: &IpAddr) -> bool {
+        match (self, ip_addr) {
+            (TargetRange::V4(start, end), IpAddr::V4(ip)) => *start <= u32::from(*ip) && u32::from(*ip) <= *end,
+            (TargetRange::V6(start, end), IpAddr::V6(ip)) => *start <= u128::from(*ip) && u128::from(*ip) <= *end,
+            (TargetRange::Octets(octets), IpAddr::V4(ip)) => {
+                octets.iter().zip(ip.octets().iter()).all(|((start, end), octet)| start <= octet && octet <= end)
+            },
+            _ => false,
+        }
+    }
+    fn get(&self, index: u128) -> IpAddr {
+        match self {
+            TargetRange::V4(start, _) => IpAddr::V4(Ipv4Addr::from(*start + index as u32)),
+            TargetRange::V6(start, _) => IpAddr::V6(Ipv6Addr::from(*start + index)),
+            TargetRange::Octets(octets) => {
+                // Last octet changes fastest
+                let mut index: u128 = index;
+                let mut ip: [u8; 4] = [0; 4];
+                for i in (0..4).rev() {
+                    let (start, end) = octets[i];
+                    let count: u128 = (end - start) as u128 + 1;
+                    ip[i] = start + (index % count) as u8;
+                    index /= count;
+                }
+                IpAddr::V4(Ipv4Addr::from(ip))
+            },
+        }
+    }
+}
+
+/// Lazy iterator over the addresses of a target specification
+///
+/// Addresses are generated on demand, so memory does not grow with the size of the address space.
+/// Overlapping targets are not deduplicated.
+#[derive(Clone, Debug)]
+pub struct TargetIter {
+    ranges: Vec<TargetRange>,
+    exclude: Vec<TargetRange>,
+    range_index: usize,
+    offset: u128,
+}
+
+impl Iterator for TargetIter {
+    type Item = IpAddr;
+    fn next(&mut self) -> Option<IpAddr> {
+        loop {
+            let range: &TargetRange = self.ranges.get(self.range_index)?;
+            if self.offset >= range.len() {
+                self.range_index += 1;
+                self.offset = 0;
+                continue;
+            }
+            let ip_addr: IpAddr = range.get(self.offset);
+            self.offset += 1;
+            if !self.exclude.iter().any(|range| range.contains(&ip_addr)) {
+                return Some(ip_addr);
+            }
+        }
+    }
+}
+
+/// Lazy source of destinations for the scanner
+///
+/// Destinations are pulled by the sender one at a time, so memory does not grow with the number of targets.
+/// Clones share the same position.
+#[derive(Clone)]
+pub struct TargetStream {
+    iter: Arc<Mutex<Box<dyn Iterator<Item = Destination> + Send>>>,
+}
+
+impl TargetStream {
+    /// Create new TargetStream from an iterator of destinations
+    pub fn new<I>(iter: I) -> TargetStream
+    where
+        I: IntoIterator<Item = Destination>,
+        I::IntoIter: Send + 'static,
+    {
+        TargetStream {
+            iter: Arc::new(Mutex::new(Box::new(iter.into_iter()))),
+        }
+    }
+    /// Create new TargetStream from target and port specifications without expanding the targets
+    ///
+    /// Hosts matched by `exclude` are skipped. Ports are selected for the scan type.
+    /// Hostnames resolve to an address of the same family as `src_ip`.
+    pub fn from_spec(targets: &str, exclude: &str, ports: &str, scan_type: &ScanType, src_ip: IpAddr) -> Result<TargetStream, String> {
+        let ports: Vec<u16> = parse_ports(ports)?.get_ports(scan_type);
+        let target_iter: TargetIter = iter_targets(targets, exclude, src_ip)?;
+        Ok(TargetStream::new(target_iter.map(move |ip_addr| Destination::new(ip_addr, ports.clone()))))
+    }
+}
+
+impl Iterator for TargetStream {
+    type Item = Destination;
+    fn next(&mut self) -> Option<Destination> {
+        self.iter.lock().unwrap().next()
+    }
+}
+
+impl fmt::Debug for TargetStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TargetStream")
+    }
+}
+
+/// Parse target specification into IP addresses
+///
+/// Comma or whitespace separated list of IP addresses, CIDR blocks ("10.0.0.0/24", "2001:db8::/120"),
+/// address ranges ("192.168.1.1-192.168.1.50"), IPv4 octet ranges ("192.168.1.1-50", "10.0.*.1") and hostnames.
+/// Hostnames resolve to an address of the same family as `src_ip`, the source IP address of the scanner.
+/// Duplicate addresses are removed.
+pub fn parse_targets(spec: &str, src_ip: IpAddr) -> Result<Vec<IpAddr>, String> {
+    let mut targets: Vec<IpAddr> = vec![];
+    let mut target_set: HashSet<IpAddr> = HashSet::new();
+    for target in split_spec(spec) {
+        let range: TargetRange = parse_target(target, src_ip)?;
+        if range.len() > MAX_TARGET_HOSTS {
+            return Err(format!("Too many hosts in target: {}", target));
+        }
+        for index in 0..range.len() {
+            let ip_addr: IpAddr = range.get(index);
+            if target_set.insert(ip_addr) {
+                targets.push(ip_addr);
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Parse target specification into a lazy iterator of IP addresses
+///
+/// Same syntax as `parse_targets`, without the limit on the number of hosts.
+/// Hosts matched by `exclude` are skipped.
+pub fn iter_targets(spec: &str, exclude: &str, src_ip: IpAddr) -> Result<TargetIter, String> {
+    let mut ranges: Vec<TargetRange> = vec![];
+    for target in split_spec(spec) {
+        ranges.push(parse_target(target, src_ip)?);
+    }
+    let mut exclude_ranges: Vec<TargetRange> = vec![];
+    for target in split_spec(exclude) {
+        exclude_ranges.push(parse_target(target, src_ip)?);
+    }
+    Ok(TargetIter {
+        ranges: ranges,
+        exclude: exclude_ranges,
+        range_index: 0,
+        offset: 0,
+    })
+}
+
+/// Parse port specification
+///
+/// Comma separated list of ports and ranges ("22,80,443,8000-8100"). Open ranges ("-1024", "60000-") are allowed.
+/// "T:" and "U:" prefixes select TCP or UDP for the following ports ("U:53,161,T:25").
+/// Ports without prefix apply to both.
+pub fn parse_ports(spec: &str) -> Result<PortSpec, String> {
+    let mut port_spec = PortSpec {
+        tcp_ports: vec![],
+        udp_ports: vec![],
+    };
+    let mut tcp_set: HashSet<u16> = HashSet::new();
+    let mut udp_set: HashSet<u16> = HashSet::new();
+    let mut protocol: PortProtocol = PortProtocol::All;
+    for token in split_spec(spec) {
+        let range: &str = match token.split_once(':') {
+            Some((prefix, range)) => {
+                protocol = match prefix {
+                    "T" | "t" => PortProtocol::Tcp,
+                    "U" | "u" => PortProtocol::Udp,
+                    _ => return Err(format!("Invalid port protocol: {}", token)),
+                };
+                range
+            },
+            None => token,
+        };
+        if range.is_empty() {
+            continue;
+        }
+        let (start_port, end_port) = parse_port_range(range)?;
+        for port in start_port..=end_port {
+            if protocol != PortProtocol::Udp && tcp_set.insert(port) {
+                port_spec.tcp_ports.push(port);
+            }
+            if protocol != PortProtocol::Tcp && udp_set.insert(port) {
+                port_spec.udp_ports.push(port);
+            }
+        }
+    }
+    Ok(port_spec)
+}
+
+/// Build destinations from target and port specifications
+///
+/// Hosts matched by `exclude` are skipped. Ports are selected for the scan type.
+/// Hostnames resolve to an address of the same family as `src_ip`.
+pub fn parse_destinations(targets: &str, exclude: &str, ports: &str, scan_type: &ScanType, src_ip: IpAddr) -> Result<Vec<Destination>, String> {
+    let mut exclude_ranges: Vec<TargetRange> = vec![];
+    for target in split_spec(exclude) {
+        exclude_ranges.push(parse_target(target, src_ip)?);
+    }
+    let ports: Vec<u16> = parse_ports(ports)?.get_ports(scan_type);
+    let mut destinations: Vec<Destination> = vec![];
+    for ip_addr in parse_targets(targets, src_ip)? {
+        if !exclude_ranges.iter().any(|range| range.contains(&ip_addr)) {
+            destinations.push(Destination::new(ip_addr, ports.clone()));
+        }
+    }
+    Ok(destinations)
+}
+
+fn split_spec(spec: &str) -> Vec<&str> {
+    spec.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()).collect()
+}
+
+fn parse_port(range: &str, port: &str) -> Result<u16, String> {
+    match port.parse::<u16>() {
+        Ok(port) => Ok(port),
+        Err(_) => Err(format!("Invalid port: {}", range)),
+    }
+}
+
+fn parse_port_range(range: &str) -> Result<(u16, u16), String> {
+    let (start_port, end_port) = match range.split_once('-') {
+        Some((start, end)) => {
+            let start_port: u16 = if start.is_empty() { 1 } else { parse_port(range, start)? };
+            let end_port: u16 = if end.is_empty() { 65535 } else { parse_port(range, end)? };
+            (start_port, end_port)
+        },
+        None => {
+            let port: u16 = parse_port(range, range)?;
+            (port, port)
+        },
+    };
+    if start_port > end_port {
+        return Err(format!("Invalid port range: {}", range));
+    }
+    Ok((start_port, end_port))
+}
+
+fn parse_target(target: &str, src_ip: IpAddr) -> Result<TargetRange, String> {
+    if let Ok(ip_addr) = target.parse::<IpAddr>() {
+        return get_cidr_range(target, ip_addr, if ip_addr.is_ipv4() { 32 } else { 128 });
+    }
+    if let Some((addr, prefix_len)) = target.split_once('/') {
+        let ip_addr: IpAddr = match addr.parse::<IpAddr>() {
+            Ok(ip_addr) => ip_addr,
+            Err(_) => resolve_host(addr, src_ip)?,
+        };
+        let prefix_len: u32 = match prefix_len.parse::<u32>() {
+            Ok(prefix_len) => prefix_len,
+            Err(_) => return Err(format!("Invalid prefix length: {}", target)),
+        };
+        return get_cidr_range(target, ip_addr, prefix_len);
+    }
+    if let Some((start, end)) = target.split_once('-') {
+        if let (Ok(start_ip), Ok(end_ip)) = (start.parse::<IpAddr>(), end.parse::<IpAddr>()) {
+            return get_address_range(target, start_ip, end_ip);
+        }
+    }
+    if is_octet_range(target) {
+        return get_octet_range(target);
+    }
+    let ip_addr: IpAddr = resolve_host(target, src_ip)?;
+    get_cidr_range(target, ip_addr, if ip_addr.is_ipv4() { 32 } else { 128 })
+}
+
+// Address of the host in the address family of the source IP address. Probes cannot be sent across families.
+fn resolve_host(host: &str, src_ip: IpAddr) -> Result<IpAddr, String> {
+    match (host, 0).to_socket_addrs() {
+        Ok(addrs) => select_source_family(host, addrs.map(|socket_addr| socket_addr.ip()), src_ip),
+        Err(_) => Err(format!("Failed to resolve host: {}", host)),
+    }
+}
+
+fn select_source_family<I: Iterator<Item = IpAddr>>(host: &str, mut addrs: I, src_ip: IpAddr) -> Result<IpAddr, String> {
+    match addrs.find(|ip_addr| ip_addr.is_ipv4() == src_ip.is_ipv4()) {
+        Some(ip_addr) => Ok(ip_addr),
+        None => Err(format!("No {} address for host: {}", if src_ip.is_ipv4() { "IPv4" } else { "IPv6" }, host)),
+    }
+}
+
+fn get_cidr_range(target: &str, ip_addr: IpAddr, prefix_len: u32) -> Result<TargetRange, String> {
+    match ip_addr {
+        IpAddr::V4(ip) => {
+            if prefix_len > 32 {
+                return Err(format!("Invalid prefix length: {}", target));
+            }
+            let host_mask: u32 = u32::MAX.checked_shr(prefix_len).unwrap_or(0);
+            let network: u32 = u32::from(ip) & !host_mask;
+            Ok(TargetRange::V4(network, network | host_mask))
+        },
+        IpAddr::V6(ip) => {
+            if prefix_len > 128 {
+                return Err(format!("Invalid prefix length: {}", target));
+            }
+            let host_mask: u128 = u128::MAX.checked_shr(prefix_len).unwrap_or(0);
+            let network: u128 = u128::from(ip) & !host_mask;
+            Ok(TargetRange::V6(network, network | host_mask))
+        },
+    }
+}
+
+fn get_address_range(target: &str, start_ip: IpAddr, end_ip: IpAddr) -> Result<TargetRange, String> {
+    match (start_ip, end_ip) {
+        (IpAddr::V4(start_ip), IpAddr::V4(end_ip)) if start_ip <= end_ip => {
+            Ok(TargetRange::V4(u32::from(start_ip), u32::from(end_ip)))
+        },
+        (IpAddr::V6(start_ip), IpAddr::V6(end_ip)) if start_ip <= end_ip => {
+            Ok(TargetRange::V6(u128::from(start_ip), u128::from(end_ip)))
+        },
+        _ => Err(format!("Invalid address range: {}", target)),
+    }
+}
+
+// IPv4 address with a range or wildcard in any octet. e.g. 192.168.1.1-50, 10.0.*.1
+fn is_octet_range(target: &str) -> bool {
+    let octets: Vec<&str> = target.split('.').collect();
+    octets.len() == 4 && octets.iter().all(|octet| !octet.is_empty() && octet.chars().all(|c| c.is_ascii_digit() || c == '-' || c == '*'))
+}
+
+fn parse_octet_range(target: &str, octet: &str) -> Result<(u8, u8), String> {
+    if octet == "*" {
+        return Ok((0, 255));
+    }
+    let (start, end) = match octet.split_once('-') {
+        Some((start, end)) => (start, end),
+        None => (octet, octet),
+    };
+    match (start.parse::<u8>(), end.parse::<u8>()) {
+        (Ok(start), Ok(end)) if start <= end => Ok((start, end)),
+        _ => Err(format!("Invalid address range: {}", target)),
+    }
+}
+
+fn get_octet_range(target: &str) -> Result<TargetRange, String> {
+    let mut octets: [(u8, u8); 4] = [(0, 0); 4];
+    for (i, octet) in target.split('.').enumerate() {
+        octets[i] = parse_octet_range(target, octet)?;
+    }
+    Ok(TargetRange::Octets(octets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[test]
+    fn open_port_ranges() {
+        let port_spec: PortSpec = parse_ports("-1024").unwrap();
+        assert_eq!(port_spec.tcp_ports, (1..=1024).collect::<Vec<u16>>());
+        assert_eq!(port_spec.udp_ports, port_spec.tcp_ports);
+        let port_spec: PortSpec = parse_ports("65530-").unwrap();
+        assert_eq!(port_spec.tcp_ports, (65530..=65535).collect::<Vec<u16>>());
+        let port_spec: PortSpec = parse_ports("-").unwrap();
+        assert_eq!(port_spec.tcp_ports.len(), 65535);
+    }
+
+    #[test]
+    fn reversed_ranges_are_rejected() {
+        assert!(parse_ports("100-10").is_err());
+        assert!(parse_targets("10.0.0.5-10.0.0.1", SRC_IP).is_err());
+        assert!(parse_targets("192.168.1.50-1", SRC_IP).is_err());
+        assert!(parse_targets("10.0.0.1-2001:db8::1", SRC_IP).is_err());
+    }
+
+    #[test]
+    fn protocol_prefix_carries_over() {
+        let port_spec: PortSpec = parse_ports("22,U:53,161,T:25,80").unwrap();
+        assert_eq!(port_spec.tcp_ports, vec![22, 25, 80]);
+        assert_eq!(port_spec.udp_ports, vec![22, 53, 161]);
+        let port_spec: PortSpec = parse_ports("U:,T:443").unwrap();
+        assert_eq!(port_spec.tcp_ports, vec![443]);
+        assert!(port_spec.udp_ports.is_empty());
+        assert!(parse_ports("X:80").is_err());
+    }
+
+    #[test]
+    fn target_forms() {
+        assert_eq!(parse_targets("10.0.0.0/30", SRC_IP).unwrap(), vec![v4(10, 0, 0, 0), v4(10, 0, 0, 1), v4(10, 0, 0, 2), v4(10, 0, 0, 3)]);
+        assert_eq!(parse_targets("10.0.*.1", SRC_IP).unwrap().len(), 256);
+        assert_eq!(parse_targets("2001:db8::/126", SRC_IP).unwrap().len(), 4);
+        // Duplicates are removed
+        assert_eq!(parse_targets("10.0.0.1,10.0.0.0/31 10.0.0.1-10.0.0.2", SRC_IP).unwrap(), vec![v4(10, 0, 0, 1), v4(10, 0, 0, 0), v4(10, 0, 0, 2)]);
+        assert!(parse_targets("10.0.0.0/33", SRC_IP).is_err());
+    }
+
+    #[test]
+    fn exclusions() {
+        let destinations: Vec<Destination> = parse_destinations("10.0.0.0/29", "10.0.0.1,10.0.0.4-10.0.0.6", "U:53,T:80", &ScanType::TcpSynScan, SRC_IP).unwrap();
+        let hosts: Vec<IpAddr> = destinations.iter().map(|dst| dst.dst_ip).collect();
+        assert_eq!(hosts, vec![v4(10, 0, 0, 0), v4(10, 0, 0, 2), v4(10, 0, 0, 3), v4(10, 0, 0, 7)]);
+        assert!(destinations.iter().all(|dst| dst.dst_ports == vec![80]));
+        let hosts: Vec<IpAddr> = iter_targets("10.0.0.0/29", "10.0.0.0/30", SRC_IP).unwrap().collect();
+        assert_eq!(hosts, vec![v4(10, 0, 0, 4), v4(10, 0, 0, 5), v4(10, 0, 0, 6), v4(10, 0, 0, 7)]);
+    }
+
+    #[test]
+    fn max_target_hosts() {
+        assert!(parse_targets("10.0.0.0/7", SRC_IP).is_err());
+        assert!(parse_targets("2001:db8::/64", SRC_IP).is_err());
+        // Not limited when streamed
+        assert_eq!(iter_targets("10.0.0.0/7", "", SRC_IP).unwrap().next(), Some(v4(10, 0, 0, 0)));
+    }
+
+    #[test]
+    fn hostname_resolves_to_source_family() {
+        let addrs: Vec<IpAddr> = vec![
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            v4(192, 168, 1, 10),
+            v4(192, 168, 1, 11),
+        ];
+        let ip_addr: IpAddr = select_source_family("example", addrs.clone().into_iter(), SRC_IP).unwrap();
+        assert_eq!(ip_addr, v4(192, 168, 1, 10));
+        let ip_addr: IpAddr = select_source_family("example", addrs.into_iter(), IpAddr::V6(Ipv6Addr::LOCALHOST)).unwrap();
+        assert_eq!(ip_addr, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        let v4_only: Vec<IpAddr> = vec![v4(192, 168, 1, 10)];
+        assert!(select_source_family("example", v4_only.into_iter(), IpAddr::V6(Ipv6Addr::LOCALHOST)).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+/// Probe timeout before any RTT sample is available
+pub(crate) const INITIAL_RTT_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Lower bound of the derived probe timeout
+pub(crate) const MIN_RTT_TIMEOUT: Duration = Duration::from_millis(100);
+/// Upper bound of the derived probe timeout
+pub(crate) const MAX_RTT_TIMEOUT: Duration = Duration::from_millis(10000);
+
+/// Smoothed RTT and RTT variance estimator (RFC 6298)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RttEstimator {
+    pub srtt: Option<Duration>,
+    pub rttvar: Duration,
+}
+
+impl RttEstimator {
+    pub fn new() -> RttEstimator {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::from_millis(0),
+        }
+    }
+    /// Update the estimate with the RTT sample
+    pub fn update(&mut self, rtt: Duration) {
+        match self.srtt {
+            Some(srtt) => {
+                let diff: Duration = srtt.abs_diff(rtt);
+                self.rttvar = (self.rttvar * 3 + diff) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            },
+            None => {
+                self.rttvar = rtt / 2;
+                self.srtt = Some(rtt);
+            },
+        }
+    }
+    /// Returns true if at least one sample has been taken
+    pub fn has_sample(&self) -> bool {
+        self.srtt.is_some()
+    }
+    /// Probe timeout derived from the estimate
+    pub fn get_timeout(&self) -> Duration {
+        match self.srtt {
+            Some(srtt) => {
+                let timeout: Duration = srtt + self.rttvar * 4;
+                if timeout < MIN_RTT_TIMEOUT {
+                    MIN_RTT_TIMEOUT
+                } else if timeout > MAX_RTT_TIMEOUT {
+                    MAX_RTT_TIMEOUT
+                } else {
+                    timeout
+                }
+            },
+            None => INITIAL_RTT_TIMEOUT,
+        }
+    }
+}
+
+/// Number of probes per evaluation window of the rate controller
+const RATE_WINDOW_SIZE: usize = 50;
+/// Back off when the response ratio of the window falls below this fraction of the average
+const BACKOFF_THRESHOLD: f64 = 0.5;
+/// Rate multiplier on back off
+const BACKOFF_FACTOR: f64 = 0.5;
+/// Rate multiplier on ramp up
+const RAMP_UP_FACTOR: f64 = 1.25;
+
+/// Congestion-aware send rate controller
+/// 
+/// Starts at the ceiling, backs off when the response ratio drops 
+/// and ramps up while responses keep flowing.
+#[derive(Clone, Debug)]
+pub(crate) struct RateController {
+    pub min_pps: f64,
+    pub max_pps: f64,
+    pub pps: f64,
+    pub next_send_time: Instant,
+    pub window_sent: usize,
+    pub window_responses: usize,
+    pub response_ratio: Option<f64>,
+}
+
+impl RateController {
+    pub fn new(min_pps: u32, max_pps: u32) -> RateController {
+        let min_pps: f64 = if min_pps == 0 { 1.0 } else { min_pps as f64 };
+        let max_pps: f64 = if (max_pps as f64) < min_pps { min_pps } else { max_pps as f64 };
+        RateController {
+            min_pps: min_pps,
+            max_pps: max_pps,
+            pps: max_pps,
+            next_send_time: Instant::now(),
+            window_sent: 0,
+            window_responses: 0,
+            response_ratio: None,
+        }
+    }
+    /// Reserve the next send slot and return the delay until it
+    pub fn next_send_delay(&mut self) -> Duration {
+        let now: Instant = Instant::now();
+        let send_time: Instant = if self.next_send_time > now { self.next_send_time } else { now };
+        self.next_send_time = send_time + Duration::from_secs_f64(1.0 / self.pps);
+        self.window_sent += 1;
+        if self.window_sent >= RATE_WINDOW_SIZE {
+            self.adjust_rate();
+        }
+        send_time - now
+    }
+    /// Count the response to a probe
+    pub fn add_response(&mut self) {
+        self.window_responses += 1;
+    }
+    fn adjust_rate(&mut self) {
+        let ratio: f64 = self.window_responses as f64 / self.window_sent as f64;
+        match self.response_ratio {
+            Some(average) => {
+                if ratio < average * BACKOFF_THRESHOLD {
+                    self.pps = (self.pps * BACKOFF_FACTOR).max(self.min_pps);
+                } else if self.window_responses > 0 {
+                    self.pps = (self.pps * RAMP_UP_FACTOR).min(self.max_pps);
+                }
+                self.response_ratio = Some(average * 0.75 + ratio * 0.25);
+            },
+            None => {
+                self.response_ratio = Some(ratio);
+            },
+        }
+        self.window_sent = 0;
+        self.window_responses = 0;
+    }
+}
+
+/// Token bucket for the hard cap on sending rate
+/// 
+/// Tokens are reserved in advance, so concurrent senders queue up behind each other 
+/// instead of bursting when the bucket is refilled.
+#[derive(Clone, Debug)]
+pub(crate) struct TokenBucket {
+    /// Tokens per second 
+    pub rate: f64,
+    /// Maximum tokens saved up while idle 
+    pub capacity: f64,
+    pub tokens: f64,
+    pub last_refill: Instant,
+    /// Cost of the packet is its length in bits instead of 1 token
+    pub per_bit: bool,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64, per_bit: bool) -> TokenBucket {
+        TokenBucket {
+            rate: rate,
+            capacity: capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+            per_bit: per_bit,
+        }
+    }
+    /// Reserve tokens for the packet and return the delay until they are available
+    pub fn reserve(&mut self, packet_len: usize) -> Duration {
+        let now: Instant = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+        let cost: f64 = if self.per_bit { (packet_len * 8) as f64 } else { 1.0 };
+        self.tokens -= cost;
+        if self.tokens >= 0.0 {
+            Duration::from_millis(0)
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}